@@ -7,13 +7,12 @@ mod args;
 
 use std::env;
 use std::fs;
-use std::path::PathBuf;
-
-use clap::Parser;
+use std::path::{Path, PathBuf};
 
 use args::Args;
 
-use omake::{Context, DefaultLogger, Env, Logger, Makefile};
+use omake::vars::Origin;
+use omake::{Context, DefaultLogger, Env, Logger, Makefile, Opts, Vars};
 
 /// An ordered list of filenames used to search for a makefile.
 const MAKEFILE_SEARCH: [&str; 6] = [
@@ -72,6 +71,21 @@ fn find_makefile() -> Option<PathBuf> {
     None
 }
 
+/// Get the path to the current program for `$(MAKE)`, as it was invoked. Note that we do not use
+/// `current_exe` because, at least on Linux, that would resolve symlinks, which is not what we want.
+/// A relative path (e.g., `./omake`) is made absolute, since sub-makes may run in other directories,
+/// but a bare name is left to be found in `PATH`.
+fn make_path() -> String {
+    let path = env::args().next().unwrap_or_else(|| "make".to_string());
+    if path.contains('/') && Path::new(&path).is_relative() {
+        if let Ok(cwd) = env::current_dir() {
+            return cwd.join(&path).display().to_string();
+        }
+    }
+
+    path
+}
+
 /// Print an error message and exit with code 2.
 fn exit_with(logger: &DefaultLogger, msg: impl AsRef<str>, context: Option<Context>) -> ! {
     logger.error(msg, context.as_ref());
@@ -79,7 +93,7 @@ fn exit_with(logger: &DefaultLogger, msg: impl AsRef<str>, context: Option<Conte
 }
 
 fn main() {
-    let args = Args::parse();
+    let args = Args::parse_with_env();
     let logger = DefaultLogger {};
 
     if args.license {
@@ -87,6 +101,16 @@ fn main() {
         return;
     }
 
+    let opts: Opts = args.clone().into();
+    if opts.debug {
+        logger.info(format!("Options: {:?}", opts), None);
+        logger.info(format!("MAKEFLAGS: {}", opts.makeflags()), None);
+    }
+
+    // Determine how the current program was invoked, for `$(MAKE)`. This must happen before
+    // changing directory, since a relative path is made absolute.
+    let make_path = make_path();
+
     // Change to another directory, if specified by the arguments.
     let original_dir = if args.directory.is_empty() {
         None
@@ -95,7 +119,8 @@ fn main() {
         let cwd = env::current_dir()
             .unwrap_or_else(|e| exit_with(&logger, format!("Failed to get cwd ({}).", e), None));
 
-        // Change to the specified directory.
+        // Change to the specified directory. Each `-C` is relative to the previous one (unless it is
+        // absolute), so `-C a/b` is equivalent to `-C a -C b`.
         let dir = args
             .directory
             .iter()
@@ -113,12 +138,8 @@ fn main() {
         Some(ref file) => PathBuf::from(file),
     };
 
-    // TODO: Use `make_path` for sub-make invocations. Use `make_name` for logging rather than the
-    // hardcoded `make`.
+    // TODO: Use `make_name` for logging rather than the hardcoded `make`.
     //
-    // // Determine how the current program was invoked. Note that we do not use `current_exe` because,
-    // // at least on Linux, that would resolve symlinks, which is not what we want.
-    // let make_path: String = env::args().next().unwrap();
     // let make_name: String = PathBuf::from(&make_path)
     //     .file_name()
     //     .unwrap()
@@ -126,20 +147,42 @@ fn main() {
     //     .into();
 
     // Parse the makefile.
-    let makefile = match Makefile::new(
-        makefile_fn,
-        args.clone().into(),
-        Box::new(DefaultLogger {}),
-        env::vars().collect::<Env>().into(),
-    ) {
+    let mut vars: Vars = env::vars().collect::<Env>().into();
+    vars.set_with_origin("MAKE", &make_path, false, Origin::Default)
+        .unwrap_or_else(|e| exit_with(&logger, e, None));
+
+    // Set `MAKECMDGOALS` to the goals given on the command line, so makefiles can check them while
+    // being parsed. As in GNU make, it's left undefined when no goals are given.
+    if !args.targets.is_empty() {
+        vars.set_with_origin(
+            "MAKECMDGOALS",
+            &args.targets.join(" "),
+            false,
+            Origin::Default,
+        )
+        .unwrap_or_else(|e| exit_with(&logger, e, None));
+    }
+    let makefile = match Makefile::new(makefile_fn, opts, Box::new(DefaultLogger {}), vars) {
         Err(e) => exit_with(&logger, e.msg, Some(e.context)),
         Ok(m) => m,
     };
 
-    // Execute the makefile.
-    if let Err(e) = makefile.execute(args.targets) {
-        exit_with(&logger, e.msg, Some(e.context));
-    }
+    // Execute the makefile (or with `-p`, just print what was parsed). The makefile is dropped
+    // before exiting so temporary files are removed.
+    let question = makefile.opts.question;
+    let out_of_date = if makefile.opts.print_database {
+        makefile.print_database();
+        false
+    } else {
+        match makefile.execute(args.targets) {
+            Ok(out_of_date) => out_of_date,
+            Err(e) => {
+                drop(makefile);
+                exit_with(&logger, e.msg, Some(e.context));
+            }
+        }
+    };
+    drop(makefile);
 
     // Go back to the original directory, if we changed directory previously.
     if let Some(cwd) = original_dir {
@@ -147,4 +190,9 @@ fn main() {
         env::set_current_dir(&cwd)
             .unwrap_or_else(|e| exit_with(&logger, format!("Chdir failed: {}.", e), None));
     }
+
+    // With `-q`, the exit code reports whether any target is out of date.
+    if question && out_of_date {
+        std::process::exit(1);
+    }
 }
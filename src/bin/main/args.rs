@@ -1,10 +1,12 @@
 //! This module provides the `clap`-based `Args` struct. This is also used for invocations of
 //! sub-make using `$(MAKE)`.
 
+use std::env;
+
 use clap::Parser;
 use const_format::formatcp;
 
-use omake::Opts;
+use omake::{DefaultLogger, Dialect, JobserverStyle, Logger, Opts};
 
 /// The `clap`-based arguments provided by this binary.
 #[derive(Clone, Debug, Parser)]
@@ -12,6 +14,8 @@ use omake::Opts;
     name = "make (oxidized)",
     version,
     about,
+    // Flags from `MAKEFLAGS` come first, so they may be repeated (e.g., `-j`) on the command line.
+    args_override_self = true,
     after_help = formatcp!(
         "License:  {}\nSource:   {}", env!("CARGO_PKG_LICENSE"), env!("CARGO_PKG_REPOSITORY")
     ),
@@ -41,10 +45,39 @@ pub struct Args {
     #[arg(short = 'C', long, value_name = "DIR")]
     pub directory: Vec<String>,
 
+    /// Print debugging information (`-d` is the same as `--debug=basic`, and `--debug=verbose`
+    /// also logs rule and file searches).
+    #[arg(
+        short,
+        long,
+        value_name = "FLAGS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "basic",
+        value_parser = ["basic", "b", "verbose", "v"],
+    )]
+    pub debug: Option<String>,
+
+    /// Print why each target's recipe runs.
+    #[arg(long)]
+    pub trace: bool,
+
+    /// Print the variables and rules read from the makefile, without making any targets.
+    #[arg(short, long)]
+    pub print_data_base: bool,
+
     /// Ignore errors from recipes.
     #[arg(short, long)]
     pub ignore_errors: bool,
 
+    /// Allow N jobs at once.
+    #[arg(short, long, value_name = "N", default_value_t = 1)]
+    pub jobs: usize,
+
+    /// Keep going when some targets can't be made.
+    #[arg(short, long)]
+    pub keep_going: bool,
+
     /// Don't execute recipes; just print them.
     #[arg(
         short = 'n',
@@ -54,6 +87,22 @@ pub struct Args {
     )]
     pub just_print: bool,
 
+    /// Run no recipes; exit with 0 if the targets are up to date, or 1 otherwise.
+    #[arg(short, long)]
+    pub question: bool,
+
+    /// Disable the built-in variable settings.
+    #[arg(short = 'R', long)]
+    pub no_builtin_variables: bool,
+
+    /// Don't echo recipes.
+    #[arg(short, long, visible_alias("quiet"))]
+    pub silent: bool,
+
+    /// Fail right after parsing if the makefile defines no rules.
+    #[arg(long)]
+    pub require_rules: bool,
+
     /// Consider FILE to be very old and do not remake it.
     #[arg(short, long, value_name = "FILE", visible_alias("assume-old"))]
     pub old_file: Vec<String>,
@@ -68,6 +117,28 @@ pub struct Args {
     )]
     pub new_file: Vec<String>,
 
+    /// Share job tokens with sub-makes using a named pipe (`fifo`) or an anonymous pipe (`pipe`).
+    #[arg(
+        long,
+        value_name = "STYLE",
+        default_value = "fifo",
+        value_parser = ["fifo", "pipe"],
+    )]
+    pub jobserver_style: String,
+
+    /// The jobserver shared by a parent `make` (internal use only).
+    #[arg(long, value_name = "AUTH", hide = true)]
+    pub jobserver_auth: Option<String>,
+
+    /// Parse makefiles using GNU or BSD syntax, or detect it from the makefile (`auto`).
+    #[arg(
+        long,
+        value_name = "DIALECT",
+        default_value = "auto",
+        value_parser = ["auto", "gnu", "bsd"],
+    )]
+    pub dialect: String,
+
     /// Print software license.
     #[arg(long)]
     pub license: bool,
@@ -77,10 +148,75 @@ impl From<Args> for Opts {
     fn from(args: Args) -> Self {
         Self {
             always_make: args.always_make,
+            debug: args.debug.is_some(),
+            debug_verbose: matches!(args.debug.as_deref(), Some("verbose" | "v")),
+            trace: args.trace,
+            print_database: args.print_data_base,
             ignore_errors: args.ignore_errors,
+            jobs: args.jobs,
+            keep_going: args.keep_going,
             just_print: args.just_print,
+            question: args.question,
+            no_builtin_variables: args.no_builtin_variables,
+            silent: args.silent,
+            capture_output: false,
+            wildcard_unsorted: false,
+            tolerant: false,
+            require_rules: args.require_rules,
             old_file: args.old_file,
             new_file: args.new_file,
+            // This `unwrap()` is safe because `clap` only accepts known styles.
+            jobserver_style: JobserverStyle::from_name(&args.jobserver_style).unwrap(),
+            jobserver_auth: args.jobserver_auth,
+            // This `unwrap()` is safe because `clap` only accepts known dialects.
+            dialect: Dialect::from_name(&args.dialect).unwrap(),
+        }
+    }
+}
+
+impl Args {
+    /// Parse the command-line arguments, merged with any flags from the `GNUMAKEFLAGS` and
+    /// `MAKEFLAGS` environment variables (e.g., set by a parent `make`). If the flags from the
+    /// environment are invalid, they are ignored.
+    pub fn parse_with_env() -> Self {
+        let mut args = env::args();
+        let program = args.next().unwrap_or_else(|| "make".to_string());
+        let args = args.collect::<Vec<_>>();
+
+        let mut merged = vec![program.clone()];
+        merged.extend(env_flags());
+        merged.extend(args.iter().cloned());
+
+        Self::try_parse_from(merged).unwrap_or_else(|_| {
+            DefaultLogger {}.warn("Ignoring invalid flags from MAKEFLAGS.", None);
+            Self::parse_from([program].into_iter().chain(args))
+        })
+    }
+}
+
+/// Get the flags from the `GNUMAKEFLAGS` and `MAKEFLAGS` environment variables as command-line
+/// arguments. The first word may be a cluster of single-letter flags without a leading dash, and
+/// any variable overrides (after `--`) are not flags, so they are skipped.
+fn env_flags() -> Vec<String> {
+    let mut flags = vec![];
+
+    for var in ["GNUMAKEFLAGS", "MAKEFLAGS"] {
+        let Ok(value) = env::var(var) else {
+            continue;
+        };
+
+        for (i, word) in value.split_whitespace().enumerate() {
+            if word == "--" || (word.contains('=') && !word.starts_with('-')) {
+                break;
+            }
+
+            if i == 0 && !word.starts_with('-') {
+                flags.push(format!("-{}", word));
+            } else {
+                flags.push(word.to_string());
+            }
         }
     }
+
+    flags
 }
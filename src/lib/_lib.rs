@@ -11,7 +11,9 @@ pub mod vars;
 
 pub use context::Context;
 pub use error::MakeError;
-pub use logger::{DefaultLogger, Logger};
+pub use logger::{BufferLogger, DefaultLogger, Logger};
 pub use makefile::opts::Opts;
-pub use makefile::Makefile;
+pub use makefile::{
+    parse_only, Dialect, FileChange, JobserverStyle, Makefile, ParseResult, RecipeTransform,
+};
 pub use vars::{Env, Vars};
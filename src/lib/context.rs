@@ -3,7 +3,7 @@
 use std::path::PathBuf;
 
 /// Track a file path, content, and location within the file (line/column).
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct Context {
     pub path: Option<PathBuf>,
     pub content: Option<String>,
@@ -27,19 +27,17 @@ impl Context {
     }
 
     pub fn label(&self) -> Option<String> {
-        let Some(path_display) = self.path.as_ref().map(|p| p.display() ) else {
-            return None;
-        };
+        let path_display = self.path.as_ref().map(|p| p.display())?;
 
         // Assume row index would only be set if line index is set. Also, increment indices by 1 to
         // convert to the traditional line/column display "number".
-        return match self.line_index {
+        match self.line_index {
             Some(line) => match self.column_index {
                 Some(column) => Some(format!("{}:{}:{}", path_display, line + 1, column + 1)),
                 None => Some(format!("{}:{}", path_display, line + 1)),
             },
             None => Some(path_display.to_string()),
-        };
+        }
     }
 
     pub fn display_line(&self) -> Option<String> {
@@ -54,7 +52,7 @@ impl Context {
                         pad = pad,
                         line_number = line_s,
                         content = content,
-                        caret_padding = " ".repeat(column),
+                        caret_padding = caret_padding(content, column),
                     ),
                     None => format!(
                         "{pad} |\n{line_number} | {content}\n{pad} | \n",
@@ -69,6 +67,16 @@ impl Context {
     }
 }
 
+/// Padding to put a caret under the character at `column` of `content`. Tabs are kept, so the caret
+/// lines up however wide tabs are displayed.
+fn caret_padding(content: &str, column: usize) -> String {
+    content
+        .chars()
+        .take(column)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect()
+}
+
 impl From<PathBuf> for Context {
     fn from(path: PathBuf) -> Self {
         let mut context = Self::new();
@@ -1,6 +1,13 @@
 //! Implementation of variable expansion.
 
-use crate::vars::Vars;
+mod functions;
+mod glob;
+
+pub use functions::{pattern_stem, run_shell, substitute_stem};
+
+use crate::context::Context;
+use crate::error::MakeError;
+use crate::vars::{Vars, AUTOMATIC_VARS};
 
 /// A stack frame used to track the previous buffer when expanding potentially nested expressions
 /// (i.e., either `$()` or `${}`). Single variable expansions (e.g., `$X`) are handled inline
@@ -9,6 +16,98 @@ struct Frame {
     pub previous_buffer: String,
     /// Which character opened this stack frame (parenthesis or brace)?
     pub opening_delimiter: char,
+    /// Byte offset of the `$` which started this expression, for pointing at it in errors.
+    pub offset: usize,
+}
+
+/// The variables for an expansion, along with the options which affect how functions behave (e.g.,
+/// from `Opts`). This is passed on to function handlers, which expand their arguments with it.
+#[derive(Clone, Copy, Debug)]
+pub struct ExpandEnv<'a> {
+    pub vars: &'a Vars,
+
+    /// List `$(wildcard)` matches in the order the filesystem lists them, rather than sorted.
+    pub wildcard_unsorted: bool,
+}
+
+impl<'a> ExpandEnv<'a> {
+    /// An environment with `vars` and the default options.
+    pub fn new(vars: &'a Vars) -> Self {
+        Self {
+            vars,
+            wildcard_unsorted: false,
+        }
+    }
+
+    /// The same options with other variables (e.g., a copy with a loop variable bound).
+    pub fn with_vars<'b>(&self, vars: &'b Vars) -> ExpandEnv<'b> {
+        ExpandEnv { vars, ..*self }
+    }
+
+    /// Run variable expansion (see [`expand`]) in this environment.
+    pub fn expand(&self, s: &str) -> Result<String, String> {
+        expand_at(s, self).map_err(|(e, _)| e)
+    }
+}
+
+/// Find the first reference to an automatic variable (e.g., `$@` or `$(@)`) in `s`, if any, so
+/// callers can warn when automatic variables are used where they are not bound. Escaped dollar signs
+/// (`$$`) are skipped.
+pub fn find_automatic_var(s: &str) -> Option<char> {
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            continue;
+        }
+
+        match chars.next() {
+            Some(ch) if AUTOMATIC_VARS.contains(&ch) => return Some(ch),
+            Some(open @ ('(' | '{')) => {
+                let close = closing_delimiter(open);
+                if let Some(&ch) = chars.peek() {
+                    if AUTOMATIC_VARS.contains(&ch) {
+                        chars.next();
+                        if chars.peek() == Some(&close) {
+                            return Some(ch);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Get the delimiter which closes an expression opened with `opening_delimiter`.
+fn closing_delimiter(opening_delimiter: char) -> char {
+    if opening_delimiter == '{' {
+        '}'
+    } else {
+        ')'
+    }
+}
+
+/// Find the byte offset of the delimiter which closes an expression opened with
+/// `opening_delimiter`, skipping over any nested expressions using the same delimiters.
+fn find_closing_delimiter(s: &str, opening_delimiter: char) -> Option<usize> {
+    let closing = closing_delimiter(opening_delimiter);
+    let mut depth: usize = 0;
+
+    for (i, c) in s.char_indices() {
+        if c == opening_delimiter {
+            depth += 1;
+        } else if c == closing {
+            if depth == 0 {
+                return Some(i);
+            }
+            depth -= 1;
+        }
+    }
+
+    None
 }
 
 /// Run variable expansion on an input string, given a collection of `vars`.
@@ -21,12 +120,46 @@ struct Frame {
 ///     expressions, where we push the current buffer onto a stack, and then continue parsing. When
 ///     we hit a matching closing delimiter (tracked on the stack frame), we evaluate the buffer,
 ///     pop the previous buffer off the stack, join it with the evaluated value, and keep going.
+///
+/// If the buffer of a long expansion is the name of a function when we hit whitespace (e.g.,
+/// `$(addprefix `), then the rest of the expression up to the matching closing delimiter is handed
+/// to the function unexpanded, since functions decide for themselves how (and whether) to expand
+/// their arguments.
 pub fn expand(s: &str, vars: &Vars) -> Result<String, String> {
+    ExpandEnv::new(vars).expand(s)
+}
+
+/// Run variable expansion in `env` like [`expand`], but on error, return a [`MakeError`] with
+/// `context`. `offset` is the byte offset of `s` within the line of `context`, if `s` is part of
+/// it. If the error is an unclosed expression in `s`, then the error's `column_index` points at the
+/// `$` which opened the expression.
+pub fn expand_with_context(
+    s: &str,
+    env: &ExpandEnv,
+    context: &Context,
+    offset: Option<usize>,
+) -> Result<String, MakeError> {
+    expand_at(s, env).map_err(|(e, at)| {
+        let mut context = context.clone();
+        context.column_index = offset.zip(at).and_then(|(offset, at)| {
+            let line = context.content.as_deref()?;
+            Some(line.get(..offset + at)?.chars().count())
+        });
+        MakeError::new(e, context)
+    })
+}
+
+/// The implementation of [`expand`], which on error also returns the byte offset in `s` of the `$`
+/// which opened an unclosed expression, if that was the error.
+fn expand_at(s: &str, env: &ExpandEnv) -> Result<String, (String, Option<usize>)> {
     let mut stack: Vec<Frame> = vec![];
     let mut current_buffer: String = String::with_capacity(s.len());
     let mut hit_variable: bool = false;
+    let mut i: usize = 0;
+
+    while let Some(c) = s[i..].chars().next() {
+        i += c.len_utf8();
 
-    for c in s.chars() {
         match c {
             '$' => {
                 hit_variable = !hit_variable;
@@ -47,6 +180,7 @@ pub fn expand(s: &str, vars: &Vars) -> Result<String, String> {
                 stack.push(Frame {
                     previous_buffer: current_buffer,
                     opening_delimiter: c,
+                    offset: i - c.len_utf8() - '$'.len_utf8(),
                 });
                 current_buffer = "".to_string();
                 hit_variable = false;
@@ -56,16 +190,15 @@ pub fn expand(s: &str, vars: &Vars) -> Result<String, String> {
                     None => current_buffer.push(c),
                     Some(f) => {
                         // Test if this character matches the opening delimiter.
-                        if (c == '}' && f.opening_delimiter == '{')
-                            || (c == ')' && f.opening_delimiter == '(')
-                        {
+                        if c == closing_delimiter(f.opening_delimiter) {
                             // Expression terminated, so expand.
-                            let var = vars.get(&current_buffer);
+                            let var = env.vars.get(&current_buffer);
                             let recursive_result: String;
 
                             // Handle recursive variable expansion.
                             let result = if var.recursive {
-                                recursive_result = expand(var.value.as_str(), vars)?;
+                                recursive_result =
+                                    env.expand(var.value.as_str()).map_err(|e| (e, None))?;
                                 &recursive_result
                             } else {
                                 &var.value
@@ -87,12 +220,45 @@ pub fn expand(s: &str, vars: &Vars) -> Result<String, String> {
             _ => {
                 // If we hit the variable indicator, then inline expansion since nesting is impossible.
                 if hit_variable {
-                    let eval = &vars.get(c.to_string()).value;
-                    current_buffer.push_str(eval);
+                    let var = env.vars.get(c.to_string());
+                    if var.recursive {
+                        let eval = env.expand(var.value.as_str()).map_err(|e| (e, None))?;
+                        current_buffer.push_str(&eval);
+                    } else {
+                        current_buffer.push_str(&var.value);
+                    }
                     hit_variable = false;
                     continue;
                 }
 
+                // Whitespace after a function name means this expression is a function call.
+                if c.is_whitespace() {
+                    if let Some(f) = stack.last() {
+                        if let Some(function) = functions::get(&current_buffer) {
+                            let rest = &s[i..];
+                            let end = find_closing_delimiter(rest, f.opening_delimiter)
+                                .ok_or_else(|| {
+                                    let e = format!("Unclosed call to function: {}", function.name);
+                                    (e, Some(f.offset))
+                                })?;
+                            let result = function
+                                .call(rest[..end].trim_start(), env)
+                                .map_err(|e| (e, None))?;
+
+                            // Skip past the closing delimiter and pop the frame.
+                            i += end + 1;
+                            current_buffer = stack.pop().unwrap().previous_buffer;
+                            current_buffer.push_str(&result);
+                            continue;
+                        }
+
+                        if functions::is_unsupported(&current_buffer) {
+                            let e = format!("Unsupported function '{}'.", current_buffer);
+                            return Err((e, None));
+                        }
+                    }
+                }
+
                 // Otherwise, just push the char.
                 current_buffer.push(c);
             }
@@ -102,9 +268,12 @@ pub fn expand(s: &str, vars: &Vars) -> Result<String, String> {
     // Return current buffer if the stack is empty, else an error.
     match stack.pop() {
         None => Ok(current_buffer),
-        Some(frame) => Err(format!(
-            "Unclosed variable: {}{}",
-            frame.opening_delimiter, frame.previous_buffer
+        Some(frame) => Err((
+            format!(
+                "Unclosed variable: {}{}",
+                frame.opening_delimiter, frame.previous_buffer
+            ),
+            Some(frame.offset),
         )),
     }
 }
@@ -195,6 +364,13 @@ mod tests {
         assert_eq!(expand("Test ${C}", &vars).unwrap(), "Test B");
     }
 
+    #[test]
+    fn test_single_char_recursive() {
+        let mut vars = Vars::new([("B", "hello")]);
+        vars.set("A", "$(B)", true).unwrap();
+        assert_eq!(expand("$A and $(A)", &vars).unwrap(), "hello and hello");
+    }
+
     #[test]
     fn test_double_recursive() {
         let mut vars = Vars::new([("A", "B")]);
@@ -228,9 +404,55 @@ mod tests {
         assert!(expand("${TEST", &vars).is_err());
     }
 
+    #[test]
+    fn test_find_automatic_var() {
+        assert_eq!(find_automatic_var("all: $@"), Some('@'));
+        assert_eq!(find_automatic_var("$(^) and ${+}"), Some('^'));
+        assert_eq!(find_automatic_var("x: $<"), Some('<'));
+        assert_eq!(find_automatic_var("$$@ $(@D) $(A)"), None);
+    }
+
+    #[test]
+    fn test_unsupported_function() {
+        let vars = Vars::new([]);
+        assert_eq!(
+            expand("$(guile (+ 1 2))", &vars).unwrap_err(),
+            "Unsupported function 'guile'."
+        );
+    }
+
     #[test]
     fn test_dollar_space_empty_replacement() {
         let vars = Vars::new([]);
         assert_eq!(expand("one$ word", &vars).unwrap(), "oneword");
     }
+
+    #[test]
+    fn test_unclosed_column() {
+        let vars = Vars::new([("B", "b")]);
+        let context = Context {
+            content: Some("A := ${B} $(B $(B)".to_string()),
+            line_index: Some(0),
+            ..Context::new()
+        };
+        let env = ExpandEnv::new(&vars);
+
+        // The column points at the `$` of the unclosed expression, within the context's line.
+        let e = expand_with_context("${B} $(B $(B)", &env, &context, Some(5)).unwrap_err();
+        assert_eq!(e.context.column_index, Some(10));
+        let e = expand_with_context("$(B", &env, &context, None).unwrap_err();
+        assert_eq!(e.context.column_index, None);
+        let e = expand_with_context("$(addprefix a,b", &env, &Context::new(), Some(0)).unwrap_err();
+        assert_eq!(e.msg, "Unclosed call to function: addprefix");
+        assert_eq!(e.context.column_index, None);
+        assert_eq!(expand_at("x $(addprefix a,b", &env).unwrap_err().1, Some(2));
+
+        // Errors from expanding a variable's value don't point into the line.
+        let mut vars = Vars::new([]);
+        vars.set("C", "$(D", true).unwrap();
+        assert_eq!(
+            expand_at("$(C)", &ExpandEnv::new(&vars)).unwrap_err().1,
+            None
+        );
+    }
 }
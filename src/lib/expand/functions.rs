@@ -0,0 +1,1164 @@
+//! Built-in functions which may be called during expansion (e.g., `$(addprefix src/,a b)`).
+//!
+//! Functions receive their arguments unexpanded, so each function is responsible for expanding the
+//! arguments it uses. This allows functions like `if` to only expand the branch that is taken.
+
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+use super::glob::glob;
+use super::ExpandEnv;
+use crate::vars::Origin;
+
+/// Signature of the handler which implements a function, given its (unexpanded) arguments.
+type Handler = fn(&[&str], &ExpandEnv) -> Result<String, String>;
+
+/// A built-in function. Arguments are separated by commas, but once `max_args` is reached, any
+/// further commas are considered part of the last argument (e.g., `$(addprefix a,b,c)` has the
+/// arguments `a` and `b,c`).
+pub struct Function {
+    pub name: &'static str,
+    pub min_args: usize,
+    pub max_args: usize,
+    handler: Handler,
+}
+
+impl Function {
+    /// Split the raw argument text and pass it to the handler.
+    pub fn call(&self, args: &str, env: &ExpandEnv) -> Result<String, String> {
+        let args = split_args(args, self.max_args);
+        if args.len() < self.min_args {
+            return Err(format!(
+                "Insufficient number of arguments ({}) to function '{}'.",
+                args.len(),
+                self.name
+            ));
+        }
+
+        (self.handler)(&args, env)
+    }
+}
+
+/// Registry of all built-in functions. To add a function, implement a [`Handler`] and register it
+/// here; the expander dispatches to it whenever an expression starts with its name followed by
+/// whitespace.
+const FUNCTIONS: &[Function] = &[
+    Function {
+        name: "addprefix",
+        min_args: 2,
+        max_args: 2,
+        handler: addprefix,
+    },
+    Function {
+        name: "addsuffix",
+        min_args: 2,
+        max_args: 2,
+        handler: addsuffix,
+    },
+    Function {
+        name: "basename",
+        min_args: 1,
+        max_args: 1,
+        handler: basename,
+    },
+    Function {
+        name: "call",
+        min_args: 1,
+        max_args: usize::MAX,
+        handler: call,
+    },
+    Function {
+        name: "dir",
+        min_args: 1,
+        max_args: 1,
+        handler: dir,
+    },
+    Function {
+        name: "file",
+        min_args: 1,
+        max_args: 2,
+        handler: file,
+    },
+    Function {
+        name: "filter",
+        min_args: 2,
+        max_args: 2,
+        handler: filter,
+    },
+    Function {
+        name: "filter-out",
+        min_args: 2,
+        max_args: 2,
+        handler: filter_out,
+    },
+    Function {
+        name: "firstword",
+        min_args: 1,
+        max_args: 1,
+        handler: firstword,
+    },
+    Function {
+        name: "flavor",
+        min_args: 1,
+        max_args: 1,
+        handler: flavor,
+    },
+    Function {
+        name: "foreach",
+        min_args: 3,
+        max_args: 3,
+        handler: foreach,
+    },
+    Function {
+        name: "if",
+        min_args: 2,
+        max_args: 3,
+        handler: if_,
+    },
+    Function {
+        name: "lastword",
+        min_args: 1,
+        max_args: 1,
+        handler: lastword,
+    },
+    Function {
+        name: "notdir",
+        min_args: 1,
+        max_args: 1,
+        handler: notdir,
+    },
+    Function {
+        name: "origin",
+        min_args: 1,
+        max_args: 1,
+        handler: origin,
+    },
+    Function {
+        name: "patsubst",
+        min_args: 3,
+        max_args: 3,
+        handler: patsubst,
+    },
+    Function {
+        name: "realpath",
+        min_args: 1,
+        max_args: 1,
+        handler: realpath,
+    },
+    Function {
+        name: "shell",
+        min_args: 1,
+        max_args: 1,
+        handler: shell,
+    },
+    Function {
+        name: "sort",
+        min_args: 1,
+        max_args: 1,
+        handler: sort,
+    },
+    Function {
+        name: "subst",
+        min_args: 3,
+        max_args: 3,
+        handler: subst,
+    },
+    Function {
+        name: "suffix",
+        min_args: 1,
+        max_args: 1,
+        handler: suffix,
+    },
+    Function {
+        name: "wildcard",
+        min_args: 1,
+        max_args: 1,
+        handler: wildcard,
+    },
+    Function {
+        name: "word",
+        min_args: 2,
+        max_args: 2,
+        handler: word,
+    },
+    Function {
+        name: "words",
+        min_args: 1,
+        max_args: 1,
+        handler: words,
+    },
+];
+
+/// Functions which GNU make provides but omake deliberately does not, so calls to them raise an
+/// error rather than silently expanding to nothing (as an undefined variable would).
+const UNSUPPORTED_FUNCTIONS: &[&str] = &["guile"];
+
+/// Whether `name` is a known function which omake does not support.
+pub fn is_unsupported(name: &str) -> bool {
+    UNSUPPORTED_FUNCTIONS.contains(&name)
+}
+
+/// Get a built-in function by name.
+pub fn get(name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find(|f| f.name == name)
+}
+
+/// Split function arguments on commas, ignoring commas inside of nested expressions, and stopping
+/// once `max_args` arguments have been found.
+fn split_args(s: &str, max_args: usize) -> Vec<&str> {
+    let mut args = vec![];
+    let mut depth: usize = 0;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 && args.len() + 1 < max_args => {
+                args.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    args.push(&s[start..]);
+    args
+}
+
+/// Apply `f` to each whitespace-separated word of `s` and join the results with single spaces.
+fn map_words(s: &str, f: impl Fn(&str) -> String) -> String {
+    s.split_whitespace().map(f).collect::<Vec<_>>().join(" ")
+}
+
+/// `$(addprefix prefix,names...)`: Prepend `prefix` to each word of `names`.
+fn addprefix(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    let prefix = env.expand(args[0])?;
+    Ok(map_words(&env.expand(args[1])?, |w| format!("{prefix}{w}")))
+}
+
+/// `$(addsuffix suffix,names...)`: Append `suffix` to each word of `names`.
+fn addsuffix(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    let suffix = env.expand(args[0])?;
+    Ok(map_words(&env.expand(args[1])?, |w| format!("{w}{suffix}")))
+}
+
+/// Split a word after its last slash into the directory (including the slash) and the rest.
+fn split_dir(word: &str) -> (&str, &str) {
+    match word.rfind('/') {
+        Some(i) => word.split_at(i + 1),
+        None => ("", word),
+    }
+}
+
+/// `$(dir names...)`: Get the directory part of each word, which is `./` for words without a slash.
+fn dir(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    Ok(map_words(&env.expand(args[0])?, |w| match split_dir(w) {
+        ("", _) => "./".to_string(),
+        (dir, _) => dir.to_string(),
+    }))
+}
+
+/// `$(notdir names...)`: Remove the directory part of each word, which leaves an empty word for
+/// words ending in a slash.
+fn notdir(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    Ok(map_words(&env.expand(args[0])?, |w| {
+        split_dir(w).1.to_string()
+    }))
+}
+
+/// Split a word before the last dot of its final path component into the name and the suffix
+/// (including the dot), if there is one.
+fn split_suffix(word: &str) -> Option<(&str, &str)> {
+    let start = split_dir(word).0.len();
+    word[start..].rfind('.').map(|i| word.split_at(start + i))
+}
+
+/// `$(basename names...)`: Remove the suffix (if any) of each word.
+fn basename(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    Ok(map_words(&env.expand(args[0])?, |w| {
+        split_suffix(w).map_or(w, |(name, _)| name).to_string()
+    }))
+}
+
+/// `$(suffix names...)`: Get the suffix of each word which has one. Words without a suffix are
+/// dropped, rather than leaving an empty word.
+fn suffix(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    Ok(env
+        .expand(args[0])?
+        .split_whitespace()
+        .filter_map(|w| split_suffix(w).map(|(_, suffix)| suffix))
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// `$(call variable,param,...)`: Expand `variable` with `$(0)` bound to its name and `$(1)`
+/// through `$(N)` bound to the (expanded) params. References to indexes beyond those provided
+/// expand to empty, even within a nested `call`, and params the macro doesn't reference are ignored.
+fn call(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    let name = env.expand(args[0])?;
+    let name = name.trim();
+
+    let mut scope = env.vars.clone();
+    scope.set_with_origin("0", name, false, Origin::Automatic)?;
+    for (i, arg) in args.iter().enumerate().skip(1) {
+        let value = env.expand(arg)?;
+        scope.set_with_origin(i.to_string(), value, false, Origin::Automatic)?;
+    }
+
+    // Clear any params bound by an outer `call` which has more params than this one.
+    let mut i = args.len();
+    while scope.lookup(i.to_string()).is_some() {
+        scope.unset(i.to_string());
+        i += 1;
+    }
+
+    let var = env.vars.get(name);
+    if var.recursive {
+        env.with_vars(&scope).expand(&var.value)
+    } else {
+        Ok(var.value.clone())
+    }
+}
+
+/// Split a `pattern` around its first `%` which is not escaped with a backslash, returning the
+/// prefix and suffix with any escaped `\%` unescaped. Returns `None` if there is no such `%`.
+fn split_pattern(pattern: &str) -> Option<(String, String)> {
+    let mut prefix = String::new();
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.clone().next() {
+                Some('%') => {
+                    prefix.push('%');
+                    chars.next();
+                }
+                // A `\\%` is a literal backslash followed by a wildcard.
+                Some('\\') if chars.clone().nth(1) == Some('%') => {
+                    prefix.push('\\');
+                    chars.next();
+                }
+                _ => prefix.push(c),
+            },
+            '%' => return Some((prefix, unescape_percent(chars.as_str()))),
+            _ => prefix.push(c),
+        }
+    }
+
+    None
+}
+
+/// Replace escaped `\%` with a literal `%`.
+fn unescape_percent(s: &str) -> String {
+    s.replace("\\%", "%")
+}
+
+/// Match `word` against a `pattern` where the first `%` matches any substring, returning that
+/// substring (the stem). A `%` escaped with a backslash matches a literal `%`. The pattern must
+/// contain an unescaped `%`. Returns `None` if the word doesn't match.
+pub fn pattern_stem<'a>(pattern: &str, word: &'a str) -> Option<&'a str> {
+    let (prefix, suffix) = split_pattern(pattern)?;
+
+    if word.len() < prefix.len() + suffix.len() {
+        return None;
+    }
+    word.strip_prefix(prefix.as_str())?
+        .strip_suffix(suffix.as_str())
+}
+
+/// Whether `word` matches `pattern`, where the first `%` matches any substring. A pattern without a
+/// `%` must match the word exactly.
+fn pattern_matches(pattern: &str, word: &str) -> bool {
+    match split_pattern(pattern) {
+        Some(_) => pattern_stem(pattern, word).is_some(),
+        None => unescape_percent(pattern) == word,
+    }
+}
+
+/// Match `word` against a `pattern` where the first `%` matches any substring (the stem), and
+/// return the `replacement` with the stem substituted for its first `%`. A pattern without a `%`
+/// must match the word exactly. Returns `None` if the word doesn't match.
+fn pattern_substitute(pattern: &str, replacement: &str, word: &str) -> Option<String> {
+    if split_pattern(pattern).is_none() {
+        return (unescape_percent(pattern) == word).then(|| unescape_percent(replacement));
+    }
+
+    let stem = pattern_stem(pattern, word)?;
+    Some(substitute_stem(replacement, stem))
+}
+
+/// Substitute `stem` for the first `%` in `pattern` (e.g., a prerequisite of a pattern rule). A
+/// `%` escaped with a backslash is a literal `%`, and a pattern without a `%` is used as is.
+pub fn substitute_stem(pattern: &str, stem: &str) -> String {
+    match split_pattern(pattern) {
+        Some((prefix, suffix)) => format!("{prefix}{stem}{suffix}"),
+        None => unescape_percent(pattern),
+    }
+}
+
+/// `$(patsubst pattern,replacement,text)`: Replace words of `text` matching `pattern` (where `%` is
+/// a wildcard) with `replacement`. Words which don't match are left unchanged.
+fn patsubst(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    let pattern = env.expand(args[0])?;
+    let replacement = env.expand(args[1])?;
+    Ok(map_words(&env.expand(args[2])?, |w| {
+        pattern_substitute(&pattern, &replacement, w).unwrap_or_else(|| w.to_string())
+    }))
+}
+
+/// `$(sort list)`: Sort the words of `list` lexicographically, removing duplicates.
+fn sort(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    let list = env.expand(args[0])?;
+    let mut words = list.split_whitespace().collect::<Vec<_>>();
+    words.sort_unstable();
+    words.dedup();
+    Ok(words.join(" "))
+}
+
+/// `$(subst from,to,text)`: Replace every occurrence of `from` in `text` with `to`. This is a plain
+/// substring replacement, so an empty `from` leaves `text` unchanged.
+fn subst(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    let from = env.expand(args[0])?;
+    let to = env.expand(args[1])?;
+    let text = env.expand(args[2])?;
+
+    if from.is_empty() {
+        return Ok(text);
+    }
+
+    Ok(text.replace(&from, &to))
+}
+
+/// Keep the words of `args[1]` which match any of the patterns in `args[0]` (if `keep`), or which
+/// match none of them (if not `keep`), preserving their order and any duplicates.
+fn filter_words(args: &[&str], env: &ExpandEnv, keep: bool) -> Result<String, String> {
+    let patterns = env.expand(args[0])?;
+    let patterns = patterns.split_whitespace().collect::<Vec<_>>();
+    Ok(env
+        .expand(args[1])?
+        .split_whitespace()
+        .filter(|w| patterns.iter().any(|p| pattern_matches(p, w)) == keep)
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// `$(filter pattern...,text)`: Keep the words of `text` which match any of the patterns.
+fn filter(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    filter_words(args, env, true)
+}
+
+/// `$(filter-out pattern...,text)`: Remove the words of `text` which match any of the patterns.
+fn filter_out(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    filter_words(args, env, false)
+}
+
+/// `$(foreach var,list,text)`: Expand `text` once for each word of `list`, with `var` bound to the
+/// word, and join the results with spaces. The binding is made in a copy of the variables (as with
+/// `call`), so any existing value of `var` is unaffected outside of the loop.
+fn foreach(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    let name = env.expand(args[0])?;
+    let name = name.trim();
+    let list = env.expand(args[1])?;
+
+    let mut scope = env.vars.clone();
+    list.split_whitespace()
+        .map(|word| {
+            scope.set_with_origin(name, word, false, Origin::Automatic)?;
+            env.with_vars(&scope).expand(args[2])
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(|results| results.join(" "))
+}
+
+/// `$(if condition,then[,else])`: Expand `then` if `condition` is non-empty, otherwise `else`. Only
+/// the part which is chosen is expanded. As in GNU make, whitespace around the condition is
+/// stripped before it is expanded, not after, so a condition which expands to only whitespace
+/// (e.g., `$(if $(space),yes,no)`) is true.
+fn if_(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    if !env.expand(args[0].trim())?.is_empty() {
+        env.expand(args[1])
+    } else {
+        args.get(2).map_or(Ok(String::new()), |e| env.expand(e))
+    }
+}
+
+/// `$(file op filename[,text])`: Read (`<`), write (`>`), or append (`>>`) to a file.
+///
+/// When reading, a single trailing newline is removed and a missing file reads as empty. When
+/// writing, a newline is added to `text` unless it already ends with one.
+fn file(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    let spec = env.expand(args[0])?;
+    let spec = spec.trim();
+    let (op, filename) = if let Some(f) = spec.strip_prefix(">>") {
+        (">>", f.trim())
+    } else if let Some(f) = spec.strip_prefix('>') {
+        (">", f.trim())
+    } else if let Some(f) = spec.strip_prefix('<') {
+        ("<", f.trim())
+    } else {
+        return Err(format!("Invalid file operation: {}", spec));
+    };
+
+    if filename.is_empty() {
+        return Err("Missing filename for file function.".to_string());
+    }
+
+    if op == "<" {
+        if args.len() > 1 {
+            return Err("Invalid argument for file function in read mode.".to_string());
+        }
+
+        return match fs::read_to_string(filename) {
+            Ok(content) => Ok(content
+                .strip_suffix('\n')
+                .map(|c| c.to_string())
+                .unwrap_or(content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(format!("Could not read file '{}' ({}).", filename, e)),
+        };
+    }
+
+    let mut text = match args.get(1) {
+        Some(text) => env.expand(text)?,
+        None => String::new(),
+    };
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+
+    fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(op == ">>")
+        .truncate(op == ">")
+        .open(filename)
+        .and_then(|mut f| f.write_all(text.as_bytes()))
+        .map_err(|e| format!("Could not write file '{}' ({}).", filename, e))?;
+
+    Ok(String::new())
+}
+
+/// `$(words text)`: Count the words in `text`. Words are separated by any (Unicode) whitespace.
+fn words(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    Ok(env.expand(args[0])?.split_whitespace().count().to_string())
+}
+
+/// `$(word n,text)`: Get the `n`th word (1-indexed) of `text`, or empty if out of range.
+fn word(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    let n = env.expand(args[0])?;
+    let n = match n.trim().parse::<usize>() {
+        Ok(0) => return Err("First argument to 'word' function must be greater than 0.".into()),
+        Ok(n) => n,
+        Err(_) => {
+            return Err(format!(
+                "Non-numeric first argument to 'word' function: '{n}'."
+            ))
+        }
+    };
+
+    Ok(env
+        .expand(args[1])?
+        .split_whitespace()
+        .nth(n - 1)
+        .unwrap_or_default()
+        .to_string())
+}
+
+/// `$(firstword names...)`: Get the first word of `names`, or empty if there are none.
+fn firstword(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    Ok(env
+        .expand(args[0])?
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_string())
+}
+
+/// `$(lastword names...)`: Get the last word of `names`, or empty if there are none.
+fn lastword(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    Ok(env
+        .expand(args[0])?
+        .split_whitespace()
+        .next_back()
+        .unwrap_or_default()
+        .to_string())
+}
+
+/// `$(wildcard pattern...)`: Find existing files matching each of the patterns.
+///
+/// The matches for each pattern are sorted so that output is deterministic, unless
+/// `wildcard_unsorted` is set (from `Opts::wildcard_unsorted`), in which case they are in the order the filesystem lists them
+/// (as with some versions of GNU make).
+fn wildcard(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    let mut result = vec![];
+
+    for pattern in env.expand(args[0])?.split_whitespace() {
+        let mut matches = glob(pattern);
+        if !env.wildcard_unsorted {
+            matches.sort();
+        }
+        result.extend(matches);
+    }
+
+    Ok(result.join(" "))
+}
+
+/// `$(shell command)`: Run `command` in the shell and return its output, with trailing newlines
+/// removed and any other newlines replaced by spaces.
+fn shell(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    run_shell(&env.expand(args[0])?, env)
+}
+
+/// Run an (already expanded) `command` in the shell, returning its output as for `$(shell)`. This is
+/// also used by the `!=` assignment operator. As with GNU make, the command's exit status is
+/// ignored, so only failing to run the shell is an error.
+pub fn run_shell(command: &str, env: &ExpandEnv) -> Result<String, String> {
+    let output = Command::new(env.expand("$(SHELL)")?)
+        .args(env.expand("$(.SHELLFLAGS)")?.split_whitespace())
+        .arg(command)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("Could not run shell ({}).", e))?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end_matches('\n')
+        .replace('\n', " "))
+}
+
+/// `$(origin variable)`: Report where `variable` was defined (e.g., `file` or `undefined`).
+fn origin(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    let name = env.expand(args[0])?;
+    Ok(env
+        .vars
+        .lookup(name)
+        .map_or(Origin::Undefined, |var| var.origin)
+        .as_str()
+        .to_string())
+}
+
+/// `$(flavor variable)`: Report how `variable` is expanded (`recursive`, `simple`, or `undefined`).
+fn flavor(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    let name = env.expand(args[0])?;
+    Ok(match env.vars.lookup(name) {
+        None => "undefined",
+        Some(var) if var.recursive => "recursive",
+        Some(_) => "simple",
+    }
+    .to_string())
+}
+
+/// `$(realpath names...)`: Get the canonical absolute path of each existing file, dropping
+/// duplicates which resolve to the same path.
+fn realpath(args: &[&str], env: &ExpandEnv) -> Result<String, String> {
+    let mut result: Vec<String> = vec![];
+
+    for name in env.expand(args[0])?.split_whitespace() {
+        if let Ok(path) = fs::canonicalize(name) {
+            let path = path.to_string_lossy().to_string();
+            if !result.contains(&path) {
+                result.push(path);
+            }
+        }
+    }
+
+    Ok(result.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expand::expand;
+    use crate::makefile::ScratchDir;
+    use crate::vars::Vars;
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(split_args("a,b,c", 2), vec!["a", "b,c"]);
+        assert_eq!(split_args("$(x a,b),c", 2), vec!["$(x a,b)", "c"]);
+        assert_eq!(split_args("a b", 1), vec!["a b"]);
+    }
+
+    #[test]
+    fn test_pattern_substitute() {
+        assert_eq!(pattern_substitute("%.c", "%.o", "a.c"), Some("a.o".into()));
+        assert_eq!(pattern_substitute("%.c", "%.o", "a.h"), None);
+        assert_eq!(pattern_substitute("a%a", "%", "a"), None);
+        assert_eq!(pattern_substitute("a%a", "[%]", "aa"), Some("[]".into()));
+        assert_eq!(pattern_substitute("a.c", "b.c", "a.c"), Some("b.c".into()));
+        assert_eq!(pattern_substitute("%", "x", "abc"), Some("x".into()));
+    }
+
+    #[test]
+    fn test_pattern_stem() {
+        assert_eq!(pattern_stem("%.o", "foo.o"), Some("foo"));
+        assert_eq!(pattern_stem("src/%.c", "src/a/b.c"), Some("a/b"));
+        assert_eq!(pattern_stem("%.o", "foo.c"), None);
+        assert_eq!(pattern_stem("foo.o", "foo.o"), None);
+    }
+
+    #[test]
+    fn test_pattern_escaped_percent() {
+        assert_eq!(pattern_stem("\\%%.c", "%a.c"), Some("a"));
+        assert_eq!(pattern_stem("\\%%.c", "a.c"), None);
+        assert_eq!(pattern_stem("a\\\\%", "a\\b"), Some("b"));
+        assert_eq!(pattern_stem("\\%", "%"), None);
+        assert_eq!(
+            pattern_substitute("100\\%", "all", "100%"),
+            Some("all".into())
+        );
+        assert_eq!(pattern_substitute("x%", "\\%%", "xy"), Some("%y".into()));
+
+        let vars = Vars::new([]);
+        assert_eq!(
+            expand("$(patsubst \\%%.txt,%,%a.txt b.txt 50%.txt)", &vars).unwrap(),
+            "a b.txt 50%.txt"
+        );
+    }
+
+    #[test]
+    fn test_substitute_stem() {
+        assert_eq!(substitute_stem("%.c", "foo"), "foo.c");
+        assert_eq!(substitute_stem("\\%%.c", "foo"), "%foo.c");
+        assert_eq!(substitute_stem("\\%.c", "foo"), "%.c");
+        assert_eq!(substitute_stem("foo.h", "foo"), "foo.h");
+    }
+
+    #[test]
+    fn test_patsubst() {
+        let vars = Vars::new([("SRC", "foo.c bar.c baz.h")]);
+        assert_eq!(
+            expand("$(patsubst %.c,%.o,foo.c bar.c baz.h)", &vars).unwrap(),
+            "foo.o bar.o baz.h"
+        );
+        assert_eq!(
+            expand("$(patsubst %.c,obj/%.o,$(SRC))", &vars).unwrap(),
+            "obj/foo.o obj/bar.o baz.h"
+        );
+        assert_eq!(
+            expand("$(patsubst baz.h,x,$(SRC))", &vars).unwrap(),
+            "foo.c bar.c x"
+        );
+        assert_eq!(
+            expand("$(patsubst %.c,%.o, a.c  b.c )", &vars).unwrap(),
+            "a.o b.o"
+        );
+    }
+
+    #[test]
+    fn test_call() {
+        let mut vars = Vars::new([]);
+        vars.set("reverse", "$(2) $(1)", true).unwrap();
+        vars.set("name", "$(0)", true).unwrap();
+        vars.set("outer", "$(1)[$(call inner,x)]", true).unwrap();
+        vars.set("inner", "$(1)$(2)", true).unwrap();
+        assert_eq!(expand("$(call reverse,a,b)", &vars).unwrap(), "b a");
+        assert_eq!(expand("$(call name)", &vars).unwrap(), "name");
+        assert_eq!(expand("$(call undefined,a)", &vars).unwrap(), "");
+        assert_eq!(expand("$(call outer,a,b)", &vars).unwrap(), "a[x]");
+    }
+
+    #[test]
+    fn test_call_higher_index_is_empty() {
+        let mut vars = Vars::new([]);
+        vars.set("m", "[$(1)|$(3)|$3]", true).unwrap();
+        assert_eq!(expand("$(call m,a)", &vars).unwrap(), "[a||]");
+    }
+
+    #[test]
+    fn test_call_extra_args_ignored() {
+        let mut vars = Vars::new([]);
+        vars.set("m", "$(1)", true).unwrap();
+        assert_eq!(expand("$(call m,a,b,c,d)", &vars).unwrap(), "a");
+        assert_eq!(expand("$(call m,a,b,$(call m,c))", &vars).unwrap(), "a");
+    }
+
+    #[test]
+    fn test_filter() {
+        let vars = Vars::new([("SRC", "foo.c bar.o baz.h foo.c")]);
+        assert_eq!(
+            expand("$(filter %.c %.h,foo.c bar.o baz.h)", &vars).unwrap(),
+            "foo.c baz.h"
+        );
+        assert_eq!(
+            expand("$(filter %.c bar.o,$(SRC))", &vars).unwrap(),
+            "foo.c bar.o foo.c"
+        );
+        assert_eq!(expand("$(filter bar,bar.o bar)", &vars).unwrap(), "bar");
+        assert_eq!(expand("[$(filter %.x,$(SRC))]", &vars).unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_filter_out() {
+        let vars = Vars::new([("SRC", "foo.c bar.o baz.h foo.c")]);
+        assert_eq!(
+            expand("$(filter-out %.o,foo.c bar.o)", &vars).unwrap(),
+            "foo.c"
+        );
+        assert_eq!(
+            expand("$(filter-out %.h bar.o,$(SRC))", &vars).unwrap(),
+            "foo.c foo.c"
+        );
+        assert_eq!(
+            expand("$(filter-out bar,bar.o bar)", &vars).unwrap(),
+            "bar.o"
+        );
+        assert_eq!(
+            expand("$(filter-out ,$(SRC))", &vars).unwrap(),
+            "foo.c bar.o baz.h foo.c"
+        );
+    }
+
+    #[test]
+    fn test_foreach() {
+        let vars = Vars::new([("list", "a b  c"), ("dirs", "x y")]);
+        assert_eq!(
+            expand("$(foreach f,a b c,$(f).o)", &vars).unwrap(),
+            "a.o b.o c.o"
+        );
+        assert_eq!(
+            expand("$(foreach f,$(list),<$f>)", &vars).unwrap(),
+            "<a> <b> <c>"
+        );
+        assert_eq!(
+            expand("$(foreach d,$(dirs),$(foreach f,1 2,$(d)/$(f)))", &vars).unwrap(),
+            "x/1 x/2 y/1 y/2"
+        );
+        assert_eq!(expand("[$(foreach f,,$(f))]", &vars).unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_foreach_shadows_variable() {
+        let mut vars = Vars::new([("f", "outer")]);
+        vars.set("show", "$(f)!", true).unwrap();
+        assert_eq!(
+            expand("$(foreach f,a b,$(show)) $(f)", &vars).unwrap(),
+            "a! b! outer"
+        );
+        assert_eq!(vars.get("f").value, "outer");
+    }
+
+    #[test]
+    fn test_if() {
+        let vars = Vars::new([("FOO", "x"), ("EMPTY", "")]);
+        assert_eq!(expand("$(if $(FOO),yes,no)", &vars).unwrap(), "yes");
+        assert_eq!(expand("$(if $(EMPTY),yes,no)", &vars).unwrap(), "no");
+        assert_eq!(expand("$(if  ,yes,no)", &vars).unwrap(), "no");
+        assert_eq!(expand("[$(if $(EMPTY),yes)]", &vars).unwrap(), "[]");
+        assert_eq!(expand("$(if $(FOO),a,b,c)", &vars).unwrap(), "a");
+        assert_eq!(expand("$(if $(EMPTY),a,b,c)", &vars).unwrap(), "b,c");
+        assert_eq!(
+            expand("$(if $(FOO),$(if $(EMPTY),1,2),3)", &vars).unwrap(),
+            "2"
+        );
+    }
+
+    #[test]
+    fn test_if_whitespace_condition() {
+        let vars = Vars::new([("space", " ")]);
+        assert_eq!(expand("$(if $(space),yes,no)", &vars).unwrap(), "yes");
+        assert_eq!(expand("$(if  $(empty) ,yes,no)", &vars).unwrap(), "no");
+    }
+
+    #[test]
+    fn test_if_only_expands_chosen_branch() {
+        let vars = Vars::new([("FOO", "x")]);
+        let scratch = ScratchDir::new().unwrap();
+        let out = scratch.path().join("out");
+        expand(
+            &format!("$(if $(FOO),,$(shell touch {}))", out.display()),
+            &vars,
+        )
+        .unwrap();
+        assert!(!out.exists());
+    }
+
+    #[test]
+    fn test_sort() {
+        let vars = Vars::new([("LIST", " c a  b a ")]);
+        assert_eq!(
+            expand("$(sort foo bar foo baz)", &vars).unwrap(),
+            "bar baz foo"
+        );
+        assert_eq!(expand("[$(sort $(LIST) c)]", &vars).unwrap(), "[a b c]");
+        assert_eq!(expand("[$(sort )]", &vars).unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_subst() {
+        let vars = Vars::new([("X", "a b c")]);
+        assert_eq!(
+            expand("$(subst ee,EE,feet on the street)", &vars).unwrap(),
+            "fEEt on the strEEt"
+        );
+        assert_eq!(expand("$(subst %,x,a% %b)", &vars).unwrap(), "ax xb");
+        assert_eq!(expand("$(subst $(EMPTY),,$(X))", &vars).unwrap(), "a b c");
+        assert_eq!(expand("$(subst ,x,$(X))", &vars).unwrap(), "a b c");
+    }
+
+    #[test]
+    fn test_subst_across_words() {
+        let vars = Vars::new([]);
+        assert_eq!(
+            expand("$(subst t o,T-O,feet on)", &vars).unwrap(),
+            "feeT-On"
+        );
+        assert_eq!(expand("$(subst a  b,x,a  b a b)", &vars).unwrap(), "x a b");
+    }
+
+    #[test]
+    fn test_addprefix_and_addsuffix() {
+        let vars = Vars::new([("NAMES", "foo bar")]);
+        assert_eq!(
+            expand("$(addprefix src/,$(NAMES))", &vars).unwrap(),
+            "src/foo src/bar"
+        );
+        assert_eq!(expand("$(addsuffix .c,foo)", &vars).unwrap(), "foo.c");
+    }
+
+    #[test]
+    fn test_addprefix_and_addsuffix_empty_arguments() {
+        let vars = Vars::new([]);
+        assert_eq!(expand("$(addprefix ,a  b)", &vars).unwrap(), "a b");
+        assert_eq!(expand("$(addsuffix .o,)", &vars).unwrap(), "");
+        assert_eq!(expand("[$(addprefix x,  )]", &vars).unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_addprefix_and_addsuffix_with_spaces() {
+        let vars = Vars::new([]);
+        assert_eq!(
+            expand("$(addprefix a b-,x y)", &vars).unwrap(),
+            "a b-x a b-y"
+        );
+        assert_eq!(expand("$(addsuffix  .o ,x y)", &vars).unwrap(), "x.o  y.o ");
+    }
+
+    #[test]
+    fn test_dir_and_notdir() {
+        let vars = Vars::new([]);
+        assert_eq!(expand("$(dir src/foo.c hacks)", &vars).unwrap(), "src/ ./");
+        assert_eq!(
+            expand("$(notdir src/foo.c hacks)", &vars).unwrap(),
+            "foo.c hacks"
+        );
+        assert_eq!(expand("$(dir a/b/c.h /x)", &vars).unwrap(), "a/b/ /");
+        assert_eq!(expand("$(notdir a/b/c.h /x)", &vars).unwrap(), "c.h x");
+        assert_eq!(expand("$(dir )", &vars).unwrap(), "");
+    }
+
+    #[test]
+    fn test_dir_and_notdir_trailing_slash() {
+        let vars = Vars::new([]);
+        assert_eq!(expand("$(dir a/b/ c)", &vars).unwrap(), "a/b/ ./");
+        assert_eq!(expand("[$(notdir a/b/)]", &vars).unwrap(), "[]");
+        assert_eq!(expand("$(notdir a/b/ c)", &vars).unwrap(), " c");
+    }
+
+    #[test]
+    fn test_basename_and_suffix() {
+        let vars = Vars::new([]);
+        assert_eq!(
+            expand("$(basename src/foo.c src-1.0/bar hacks.tar.gz)", &vars).unwrap(),
+            "src/foo src-1.0/bar hacks.tar"
+        );
+        assert_eq!(
+            expand("$(suffix src/foo.c src-1.0/bar hacks.tar.gz)", &vars).unwrap(),
+            ".c .gz"
+        );
+        assert_eq!(expand("$(basename foo)", &vars).unwrap(), "foo");
+        assert_eq!(expand("[$(suffix foo bar)]", &vars).unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_basename_and_suffix_dots_in_directories() {
+        let vars = Vars::new([]);
+        assert_eq!(expand("[$(suffix dir.d/foo)]", &vars).unwrap(), "[]");
+        assert_eq!(expand("$(basename dir.d/foo)", &vars).unwrap(), "dir.d/foo");
+        assert_eq!(expand("$(suffix dir/.bashrc)", &vars).unwrap(), ".bashrc");
+        assert_eq!(expand("$(basename dir/.bashrc)", &vars).unwrap(), "dir/");
+    }
+
+    #[test]
+    fn test_insufficient_arguments() {
+        let vars = Vars::new([]);
+        assert!(expand("$(addprefix x)", &vars).is_err());
+    }
+
+    #[test]
+    fn test_file_read() {
+        let scratch = ScratchDir::new().unwrap();
+        let with_newline = scratch.path().join("with_newline");
+        let without_newline = scratch.path().join("without_newline");
+        fs::write(&with_newline, "a\nb\n").unwrap();
+        fs::write(&without_newline, "a\nb").unwrap();
+
+        let vars = Vars::new([]);
+        for path in [&with_newline, &without_newline] {
+            let s = format!("[$(file < {})]", path.display());
+            assert_eq!(expand(&s, &vars).unwrap(), "[a\nb]");
+        }
+    }
+
+    #[test]
+    fn test_file_read_missing() {
+        let vars = Vars::new([]);
+        assert_eq!(
+            expand("[$(file <omake_file_that_does_not_exist)]", &vars).unwrap(),
+            "[]"
+        );
+    }
+
+    #[test]
+    fn test_file_write_and_append() {
+        let scratch = ScratchDir::new().unwrap();
+        let path = scratch.path().join("out");
+        let vars = Vars::new([]);
+        let path_display = path.display();
+        assert_eq!(
+            expand(&format!("$(file >{path_display},a)"), &vars).unwrap(),
+            ""
+        );
+        expand(&format!("$(file >>{path_display},b)"), &vars).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\nb\n");
+    }
+
+    #[test]
+    fn test_wildcard_sorted() {
+        let scratch = ScratchDir::new().unwrap();
+        let dir = scratch.path();
+        for f in ["c.c", "a.c", "b.c", "d.h"] {
+            fs::write(dir.join(f), "").unwrap();
+        }
+
+        let vars = Vars::new([]);
+        let d = dir.display();
+        let expected = format!("{d}/a.c {d}/b.c {d}/c.c");
+        for _ in 0..3 {
+            assert_eq!(
+                expand(&format!("$(wildcard {d}/*.c)"), &vars).unwrap(),
+                expected
+            );
+        }
+        assert_eq!(
+            expand(&format!("$(wildcard {d}/*.o {d}/d.h)"), &vars).unwrap(),
+            format!("{d}/d.h")
+        );
+    }
+
+    #[test]
+    fn test_wildcard_no_match() {
+        let vars = Vars::new([]);
+        assert_eq!(
+            expand("$(wildcard omake_does_not_exist/*.c)", &vars).unwrap(),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_realpath_dedup() {
+        let scratch = ScratchDir::new().unwrap();
+        let dir = scratch.path();
+        fs::write(dir.join("a"), "").unwrap();
+
+        let vars = Vars::new([]);
+        let d = dir.display();
+        assert_eq!(
+            expand(&format!("$(realpath {d}/a {d}/./a {d}/missing)"), &vars).unwrap(),
+            fs::canonicalize(dir.join("a"))
+                .unwrap()
+                .display()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_words_and_word() {
+        let vars = Vars::new([]);
+        assert_eq!(expand("$(words foo bar baz)", &vars).unwrap(), "3");
+        assert_eq!(expand("$(words )", &vars).unwrap(), "0");
+        assert_eq!(expand("$(word 2,foo bar baz)", &vars).unwrap(), "bar");
+        assert_eq!(expand("$(word 4,foo bar baz)", &vars).unwrap(), "");
+        assert!(expand("$(word 0,foo)", &vars).is_err());
+        assert!(expand("$(word x,foo)", &vars).is_err());
+    }
+
+    #[test]
+    fn test_word_boundaries() {
+        let vars = Vars::new([("L", "foo bar baz"), ("EMPTY", "")]);
+        assert_eq!(expand("$(word 1,$(L))", &vars).unwrap(), "foo");
+        assert_eq!(expand("$(word 3,$(L))", &vars).unwrap(), "baz");
+        assert_eq!(expand("$(word 1,$(EMPTY))", &vars).unwrap(), "");
+        assert_eq!(expand("$(words $(EMPTY))", &vars).unwrap(), "0");
+    }
+
+    #[test]
+    fn test_firstword_and_lastword() {
+        let vars = Vars::new([("L", " foo bar  baz "), ("EMPTY", "")]);
+        assert_eq!(expand("$(firstword $(L))", &vars).unwrap(), "foo");
+        assert_eq!(expand("$(lastword $(L))", &vars).unwrap(), "baz");
+        assert_eq!(
+            expand("$(firstword foo)-$(lastword foo)", &vars).unwrap(),
+            "foo-foo"
+        );
+        assert_eq!(expand("[$(firstword $(EMPTY))]", &vars).unwrap(), "[]");
+        assert_eq!(expand("[$(lastword $(EMPTY))]", &vars).unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_words_and_word_unicode() {
+        // Words containing multibyte characters, separated by a no-break space and an ideographic
+        // space, which `split_whitespace` treats as whitespace.
+        let vars = Vars::new([("W", "héllo\u{a0}wörld\u{3000}日本語")]);
+        assert_eq!(expand("$(words $(W))", &vars).unwrap(), "3");
+        assert_eq!(expand("$(word 2,$(W))", &vars).unwrap(), "wörld");
+        assert_eq!(expand("$(word 3,$(W))", &vars).unwrap(), "日本語");
+        assert_eq!(expand("$(addprefix é,ü ö)", &vars).unwrap(), "éü éö");
+    }
+
+    #[test]
+    fn test_shell() {
+        let vars = Vars::new([("X", "a")]);
+        assert_eq!(expand("$(shell echo $(X) b)", &vars).unwrap(), "a b");
+        assert_eq!(
+            expand("$(shell printf 'a\\nb\\n\\n')", &vars).unwrap(),
+            "a b"
+        );
+
+        // A failing command is not an error, but failing to run the shell at all is.
+        assert_eq!(expand("$(shell false)", &vars).unwrap(), "");
+
+        // The shell and its flags may be recursively-expanded.
+        let mut vars = Vars::new([("SH", "/bin/sh"), ("FLAGS", "-c")]);
+        vars.set("SHELL", "$(SH)", true).unwrap();
+        vars.set(".SHELLFLAGS", "$(FLAGS)", true).unwrap();
+        assert_eq!(expand("$(shell echo a)", &vars).unwrap(), "a");
+
+        let vars = Vars::new([("SHELL", "/omake/does/not/exist")]);
+        assert!(expand("$(shell echo a)", &vars)
+            .unwrap_err()
+            .starts_with("Could not run shell"));
+    }
+
+    #[test]
+    fn test_origin() {
+        let mut vars = Vars::new([("A", "B")]);
+        vars.set("A_NAME", "A", false).unwrap();
+        assert_eq!(expand("$(origin A)", &vars).unwrap(), "file");
+        assert_eq!(expand("$(origin $(A_NAME))", &vars).unwrap(), "file");
+        assert_eq!(expand("$(origin CC)", &vars).unwrap(), "default");
+        assert_eq!(expand("$(origin UNSET)", &vars).unwrap(), "undefined");
+    }
+
+    #[test]
+    fn test_flavor() {
+        let mut vars = Vars::new([("A", "a"), ("EMPTY", "")]);
+        vars.set("R", "$(A)", true).unwrap();
+        assert_eq!(expand("$(flavor A)", &vars).unwrap(), "simple");
+        assert_eq!(expand("$(flavor R)", &vars).unwrap(), "recursive");
+        assert_eq!(expand("$(flavor EMPTY)", &vars).unwrap(), "simple");
+        assert_eq!(expand("$(flavor UNSET)", &vars).unwrap(), "undefined");
+        assert_eq!(expand("$(origin EMPTY)", &vars).unwrap(), "file");
+    }
+}
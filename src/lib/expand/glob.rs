@@ -0,0 +1,140 @@
+//! Minimal filesystem globbing for the `wildcard` function, supporting `*`, `?`, and bracket
+//! expressions (e.g., `[a-z]` or `[!0-9]`) within each path component.
+
+use std::fs;
+use std::path::Path;
+
+/// Characters which make a path component a pattern rather than a literal name.
+const GLOB_CHARS: [char; 3] = ['*', '?', '['];
+
+/// Find all existing paths matching `pattern`, in the order the filesystem lists them.
+pub fn glob(pattern: &str) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    for component in rest.split('/') {
+        let mut next = vec![];
+
+        for path in paths {
+            // Literal components (and empty ones from repeated or trailing slashes) are just
+            // appended, and checked for existence at the end.
+            if !component.contains(GLOB_CHARS) {
+                next.push(join(&path, component));
+                continue;
+            }
+
+            let dir = if path.is_empty() { "." } else { path.as_str() };
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+
+                // Hidden files are only matched by patterns which explicitly start with a `.`.
+                if name.starts_with('.') && !component.starts_with('.') {
+                    continue;
+                }
+
+                if matches(component, &name) {
+                    next.push(join(&path, &name));
+                }
+            }
+        }
+
+        paths = next;
+    }
+
+    paths
+        .into_iter()
+        .filter(|p| !p.is_empty() && Path::new(p).exists())
+        .collect()
+}
+
+/// Join a path component onto a path, without adding a leading `./` to relative paths.
+fn join(path: &str, component: &str) -> String {
+    if path.is_empty() {
+        component.to_string()
+    } else if path.ends_with('/') {
+        format!("{}{}", path, component)
+    } else {
+        format!("{}/{}", path, component)
+    }
+}
+
+/// Test whether a single path component `name` matches the glob `pattern`.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    matches_chars(&pattern, &name)
+}
+
+fn matches_chars(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|i| matches_chars(&pattern[1..], &name[i..])),
+        Some('?') => !name.is_empty() && matches_chars(&pattern[1..], &name[1..]),
+        Some('[') => {
+            let Some((&ch, rest)) = name.split_first() else {
+                return false;
+            };
+            match match_bracket(&pattern[1..], ch) {
+                Some((true, len)) => matches_chars(&pattern[1 + len..], rest),
+                Some((false, _)) => false,
+                // An unterminated bracket is just a literal `[`.
+                None => ch == '[' && matches_chars(&pattern[1..], rest),
+            }
+        }
+        Some('\\') if pattern.len() > 1 => {
+            name.first() == Some(&pattern[1]) && matches_chars(&pattern[2..], &name[1..])
+        }
+        Some(&p) => name.first() == Some(&p) && matches_chars(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Match `ch` against a bracket expression (with the opening `[` already consumed). Returns
+/// whether it matched, and the length of the expression including the closing `]`, or `None` if
+/// the expression is unterminated.
+fn match_bracket(pattern: &[char], ch: char) -> Option<(bool, usize)> {
+    let negated = matches!(pattern.first(), Some('!' | '^'));
+    let mut i = usize::from(negated);
+    let mut matched = false;
+    let mut first = true;
+
+    while i < pattern.len() {
+        let c = pattern[i];
+
+        // A `]` immediately after the opening (or negation) is a literal.
+        if c == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            matched |= c <= ch && ch <= pattern[i + 2];
+            i += 3;
+        } else {
+            matched |= c == ch;
+            i += 1;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("*.c", "foo.c"));
+        assert!(!matches("*.c", "foo.h"));
+        assert!(matches("f?o.[ch]", "foo.h"));
+        assert!(matches("[!a-e]*", "foo"));
+        assert!(!matches("[!a-z]*", "foo"));
+        assert!(matches("\\*", "*"));
+    }
+}
@@ -1,5 +1,8 @@
 //! Generic logging facility with a default implementation.
 
+use std::io::{self, Write};
+use std::sync::Mutex;
+
 use crate::context::Context;
 
 pub const INFO: &str = "INFO";
@@ -28,6 +31,24 @@ pub trait Logger {
         self.write(self.format_log(ERROR, msg, context));
     }
 
+    /// Log why a target is being remade (under `--trace`). Like GNU `make`, this goes to `stdout`
+    /// by default, so it is interleaved with the echoed recipes.
+    fn trace(&self, msg: impl AsRef<str>, context: Option<&Context>) {
+        let _ = writeln!(io::stdout(), "{}", self.format_log(INFO, msg, context));
+    }
+
+    /// Handle a line of output from a recipe (including echoed commands), when recipe output is
+    /// captured (see `Opts::capture_output`). By default, it's printed to `stdout`.
+    fn recipe_stdout(&self, line: &str) {
+        let _ = writeln!(io::stdout(), "{}", line);
+    }
+
+    /// Handle a line of error output from a recipe, when recipe output is captured. By default,
+    /// it's printed to `stderr`.
+    fn recipe_stderr(&self, line: &str) {
+        let _ = writeln!(io::stderr(), "{}", line);
+    }
+
     /// Formatter for all log messages.
     fn format_log(&self, level: &str, msg: impl AsRef<str>, context: Option<&Context>) -> String {
         // Format log level and context label/line.
@@ -59,9 +80,34 @@ pub trait Logger {
 /// Uses the default implementation and outputs to `stderr`.
 pub struct DefaultLogger {}
 
-/// By default, print to `stderr`.
+/// By default, print to `stderr`, ignoring write errors (e.g., a broken pipe).
 impl Logger for DefaultLogger {
     fn write(&self, msg: String) {
-        eprintln!("{}", msg);
+        let _ = writeln!(io::stderr(), "{}", msg);
+    }
+}
+
+/// Collects formatted messages in memory rather than printing them, e.g., so tooling can inspect
+/// the diagnostics from parsing a makefile.
+#[derive(Debug, Default)]
+pub struct BufferLogger {
+    messages: Mutex<Vec<String>>,
+}
+
+impl BufferLogger {
+    /// Consume the logger and return the messages written to it.
+    pub fn into_messages(self) -> Vec<String> {
+        self.messages
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Logger for BufferLogger {
+    fn write(&self, msg: String) {
+        self.messages
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(msg);
     }
 }
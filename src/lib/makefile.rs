@@ -1,25 +1,49 @@
 //! The core logic for parsing and executing makefiles.
 
+pub mod dialect;
+pub mod exec_context;
+#[cfg(unix)]
+pub mod jobserver;
+#[cfg(not(unix))]
+#[path = "makefile/unsupported_jobserver.rs"]
+pub mod jobserver;
 pub mod opts;
+pub mod parallel;
 pub mod rule_map;
+pub mod scratch_dir;
 
-pub use opts::Opts;
+pub use dialect::Dialect;
+pub use exec_context::{ExecContext, FileChange};
+pub use jobserver::Jobserver;
+pub use opts::{JobserverStyle, Opts};
+pub use scratch_dir::ScratchDir;
 
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{fs, fs::File};
 
 use crate::context::Context;
 use crate::error::MakeError;
-use crate::expand::expand;
-use crate::logger::Logger;
-use crate::vars::Vars;
+use crate::expand::{expand_with_context, find_automatic_var, run_shell, ExpandEnv};
+use crate::logger::{BufferLogger, Logger};
+use crate::vars::{Origin, Vars};
 
-use rule_map::{Rule, RuleMap};
+use dialect::{bsd_directive, bsd_function, split_comparison, split_logical};
+use rule_map::{Freshness, Rule, RuleMap};
 
 const COMMENT_INDICATOR: char = '#';
 
+/// Whether `name` can be passed to recipes as an environment variable (i.e., it's made of letters,
+/// digits, and underscores, and doesn't start with a digit).
+fn is_env_name(name: &str) -> bool {
+    !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 // struct PhysicalLine {
 //     content: String,
 //     index: usize,
@@ -31,48 +55,426 @@ const COMMENT_INDICATOR: char = '#';
 //     breaks: Vec<usize>,
 // }
 
+/// Variable assignment operators. Each is checked against the text just before the first `=`, so
+/// longer operators must come first (e.g., `::=` before `:=`).
+const ASSIGNMENT_OPERATORS: [&str; 7] = [":::=", "::=", ":=", "+=", "?=", "!=", "="];
+
+/// If `s` is a variable assignment (e.g., `VAR := value`), split it into the name, the operator,
+/// and the value (neither of which are trimmed). It's not an assignment if there is a `:` before
+/// the operator, since then it's a rule (e.g., `target: VAR = value` or `target: a=b`).
+fn split_assignment(s: &str) -> Option<(&str, &'static str, &str)> {
+    let equals = s.find('=')?;
+    let (before, value) = (&s[..equals], &s[equals + 1..]);
+    let op = ASSIGNMENT_OPERATORS
+        .into_iter()
+        .find(|op| before.ends_with(&op[..op.len() - 1]))
+        .expect("`=` always matches");
+    let name = &before[..before.len() + 1 - op.len()];
+
+    if name.contains(':') {
+        return None;
+    }
+
+    Some((name, op, value))
+}
+
+/// Whether `line` ends with an unescaped backslash, meaning it continues onto the next line.
+fn is_continued(line: &str) -> bool {
+    line.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1
+}
+
+/// The byte offset of `s` within `line`, if `s` is a slice of `line` (e.g., from `split_once()` or
+/// `trim()`), rather than a separate string.
+fn offset_in(line: &str, s: &str) -> Option<usize> {
+    let offset = (s.as_ptr() as usize).checked_sub(line.as_ptr() as usize)?;
+    (offset + s.len() <= line.len()).then_some(offset)
+}
+
+/// Strip a trailing comment from a line which is not a recipe line. A `#` starts a comment unless
+/// it's escaped as `\#` (which becomes a literal `#`), or it's within a variable reference or
+/// function call (e.g., `$(subst #,-,$(A))`). In a rule, the recipe after a `;` (e.g., `a: ; cmd`)
+/// is passed to the shell as it is, but a `;` in a variable value doesn't start a recipe.
+fn strip_comment(line: &str) -> Cow<'_, str> {
+    if !line.contains('#') {
+        return Cow::Borrowed(line);
+    }
+
+    let assignment = split_assignment(line).is_some();
+    let mut stripped = String::with_capacity(line.len());
+    let mut depth: usize = 0;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, c)| c);
+        match (c, next) {
+            ('\\', Some('#')) => {
+                stripped.push('#');
+                chars.next();
+                continue;
+            }
+            ('$', Some(next @ ('$' | '(' | '{'))) => {
+                depth += usize::from(next != '$');
+                stripped.push(c);
+                stripped.push(next);
+                chars.next();
+                continue;
+            }
+            ('(' | '{', _) if depth > 0 => depth += 1,
+            (')' | '}', _) if depth > 0 => depth -= 1,
+            ('#', _) if depth == 0 => break,
+            (';', _) if depth == 0 && !assignment => {
+                stripped.push_str(&line[i..]);
+                break;
+            }
+            _ => {}
+        }
+        stripped.push(c);
+    }
+
+    Cow::Owned(stripped)
+}
+
+/// Directives which open a conditional.
+const CONDITIONAL_DIRECTIVES: [&str; 4] = ["ifeq", "ifneq", "ifdef", "ifndef"];
+
+/// Split the arguments of a conditional such as `ifeq`, which are either parenthesized and
+/// separated by a comma (e.g., `(a,b)`), or quoted and separated by whitespace (e.g., `"a" 'b'`).
+/// Whitespace around parenthesized arguments is ignored.
+fn conditional_args(args: &str) -> Option<(&str, &str)> {
+    let args = args.trim();
+
+    if let Some(inner) = args.strip_prefix('(').and_then(|a| a.strip_suffix(')')) {
+        // Find the comma which is not within a nested expression.
+        let mut depth: usize = 0;
+        for (i, c) in inner.char_indices() {
+            match c {
+                '(' | '{' => depth += 1,
+                ')' | '}' => depth = depth.saturating_sub(1),
+                ',' if depth == 0 => return Some((inner[..i].trim(), inner[i + 1..].trim())),
+                _ => {}
+            }
+        }
+        return None;
+    }
+
+    let quoted = |s: &str| -> Option<(usize, usize)> {
+        let quote = s.chars().next().filter(|&c| c == '"' || c == '\'')?;
+        let end = s[1..].find(quote)? + 1;
+        Some((1, end))
+    };
+    let (start, end) = quoted(args)?;
+    let a = &args[start..end];
+    let rest = args[end + 1..].trim_start();
+    let (start, end) = quoted(rest)?;
+    if !rest[end + 1..].trim().is_empty() {
+        return None;
+    }
+
+    Some((a, &rest[start..end]))
+}
+
+/// If `line` is the directive `name` (i.e., the first word is `name` and the line is not a variable
+/// assignment such as `include = x`), then return the rest of the line.
+fn directive<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let rest = line.trim_start().strip_prefix(name)?;
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let rest = rest.trim();
+    if ["=", ":=", "::=", "+=", "?=", "!="]
+        .iter()
+        .any(|op| rest.starts_with(op))
+    {
+        return None;
+    }
+
+    Some(rest)
+}
+
+/// The result of parsing a makefile without executing it, for tooling such as linters.
+#[derive(Debug)]
+pub struct ParseResult {
+    pub rule_map: RuleMap,
+    pub vars: Vars,
+
+    /// The makefiles which were parsed, in the order they were read (i.e., `MAKEFILE_LIST`).
+    pub makefile_list: Vec<String>,
+
+    /// Formatted warnings emitted while parsing.
+    pub warnings: Vec<String>,
+
+    /// Errors from parsing, in the order they were found (only more than one when `tolerant`).
+    pub errors: Vec<MakeError>,
+}
+
+/// Parse a makefile without executing it, collecting warnings rather than logging them. Under
+/// `opts.tolerant`, parse errors are collected in the result rather than stopping the parse.
+pub fn parse_only(path: PathBuf, opts: Opts, vars: Vars) -> Result<ParseResult, MakeError> {
+    let mut makefile = Makefile::read(path, opts, Box::new(BufferLogger::default()), vars)?;
+    let errors = std::mem::take(&mut makefile.parse_errors);
+
+    Ok(ParseResult {
+        rule_map: makefile.rule_map,
+        vars: makefile.vars,
+        makefile_list: makefile.makefile_list,
+        warnings: makefile.logger.into_messages(),
+        errors,
+    })
+}
+
+/// State of a conditional (e.g., `ifeq`) whose `endif` has not been reached yet.
+#[derive(Debug)]
+struct Conditional {
+    /// Whether lines in the current branch are parsed.
+    active: bool,
+
+    /// Whether any branch has been taken, so later `else` branches are skipped. This is also set
+    /// for conditionals nested within a skipped branch, so none of their branches are taken.
+    taken: bool,
+
+    /// Whether the final `else` has been seen, so no further `else` is allowed.
+    else_seen: bool,
+
+    /// Where the conditional was opened, for reporting a missing `endif`.
+    context: Context,
+}
+
+/// A transformation applied to each command before it is passed to the shell.
+pub type RecipeTransform = fn(&str) -> String;
+
 /// The primary interface for reading, parsing, and executing a makefile.
 #[derive(Debug)]
 pub struct Makefile<L: Logger> {
     pub opts: Opts,
     pub logger: Box<L>,
 
+    /// Optional transformation of commands before they are run, which is off by default.
+    pub recipe_transform: Option<RecipeTransform>,
+
     rule_map: RuleMap,
     default_target: Option<String>,
 
+    /// Whether the default target was set with `set_default_goal`, rather than being the first
+    /// normal target.
+    default_target_explicit: bool,
+
+    /// Target-specific variables (e.g., `target: VAR = value`), which are set while executing the
+    /// target's recipe, along with whether each is recursive.
+    target_vars: HashMap<String, Vec<(String, String, bool)>>,
+
+    /// The dialect of the makefile (and any makefiles it includes), which is never `Auto`.
+    dialect: Dialect,
+
+    /// Variables exported to the environment of recipes with `export`.
+    exported: HashSet<String>,
+
+    /// Variables removed from the environment of recipes with `unexport`, even if they came from
+    /// the environment (or would be exported by `export_all`).
+    unexported: HashSet<String>,
+
+    /// Whether a bare `export` exported every variable defined in a makefile.
+    export_all: bool,
+
     // Parser state.
     pub vars: Vars,
     current_rule: Option<Rule>,
     context: Context,
+
+    /// A non-recipe logical line which was continued with a trailing backslash.
+    continued_line: Option<String>,
+
+    /// Conditionals which are open at the current line, the innermost last.
+    conditionals: Vec<Conditional>,
+
+    /// Where the previous line was a variable assignment, if it was, for diagnosing a recipe line
+    /// which follows it.
+    previous_assignment: Option<Context>,
+
+    /// Makefiles currently being parsed (the outermost first), used to detect include cycles.
+    include_stack: Vec<PathBuf>,
+
+    /// All makefiles which have been parsed, in order, exposed as `MAKEFILE_LIST`.
+    makefile_list: Vec<String>,
+
+    /// Errors collected while parsing under `opts.tolerant`.
+    parse_errors: Vec<MakeError>,
+
+    /// Scratch directory for temporary files, created on first use and removed on drop.
+    scratch_dir: OnceLock<ScratchDir>,
+
+    /// The jobserver for `-j`, set up on first use (`None` when running one job at a time).
+    jobserver: OnceLock<Option<Jobserver>>,
+
+    /// Where files were found in the `VPATH` directories (if they were), so each file is only
+    /// searched for (and logged under `--debug=verbose`) once.
+    vpath_cache: Mutex<HashMap<String, Option<String>>>,
 }
 
 impl<L: Logger> Makefile<L> {
-    /// Principal interface for reading and parsing a makefile.
+    /// Principal interface for reading and parsing a makefile. Under `opts.tolerant`, every parse
+    /// error is logged, and the last is returned.
     pub fn new(path: PathBuf, opts: Opts, logger: Box<L>, vars: Vars) -> Result<Self, MakeError> {
-        // Initialize the `Makefile` struct with default values.
-        let mut makefile = Self {
+        Self::read(path, opts, logger, vars)?.report_parse_errors()
+    }
+
+    /// Parse a makefile from any reader (e.g., an in-memory string), rather than a file. Errors
+    /// have no path in their context, and relative `include`s are resolved against the current
+    /// directory. Since there is no name to detect it from, the dialect defaults to GNU.
+    pub fn from_reader<R: BufRead>(
+        reader: R,
+        opts: Opts,
+        logger: Box<L>,
+        vars: Vars,
+    ) -> Result<Self, MakeError> {
+        let dialect = match opts.dialect {
+            Dialect::Auto => Dialect::Gnu,
+            dialect => dialect,
+        };
+        let mut makefile = Self::init(opts, logger, vars, dialect, Context::new())?;
+        makefile.parse(reader)?;
+        makefile.check_rules()?;
+        makefile.report_parse_errors()
+    }
+
+    /// Read and parse a makefile, leaving any errors collected under `opts.tolerant` in
+    /// `parse_errors`.
+    fn read(path: PathBuf, opts: Opts, logger: Box<L>, vars: Vars) -> Result<Self, MakeError> {
+        let dialect = opts.dialect.resolve(&path);
+        let mut makefile = Self::init(opts, logger, vars, dialect, path.clone().into())?;
+
+        // Open the makefile and run it through the parser.
+        makefile.parse_file(path, false)?;
+        makefile.check_rules()?;
+
+        Ok(makefile)
+    }
+
+    /// Create a `Makefile` with nothing parsed yet.
+    fn init(
+        opts: Opts,
+        logger: Box<L>,
+        mut vars: Vars,
+        dialect: Dialect,
+        context: Context,
+    ) -> Result<Self, MakeError> {
+        if opts.no_builtin_variables {
+            vars.clear_builtins();
+        }
+        vars.set("MAKEFLAGS".to_string(), opts.makeflags(), false)
+            .map_err(|e| MakeError::new(e, Context::new()))?;
+
+        // `CURDIR` is the working directory (i.e., after any `-C`), which is also where commands
+        // such as `$(shell)` run, since changing directory changes the process's directory.
+        if let Ok(cwd) = std::env::current_dir() {
+            vars.set("CURDIR".to_string(), cwd.display().to_string(), false)
+                .map_err(|e| MakeError::new(e, Context::new()))?;
+        }
+
+        Ok(Self {
             opts,
-            logger: logger,
+            logger,
+            recipe_transform: None,
             rule_map: RuleMap::new(),
             default_target: None,
-            vars: vars,
+            default_target_explicit: false,
+            target_vars: HashMap::new(),
+            exported: HashSet::new(),
+            unexported: HashSet::new(),
+            export_all: false,
+            dialect,
+            vars,
             current_rule: None,
-            context: path.clone().into(),
+            context,
+            continued_line: None,
+            conditionals: vec![],
+            previous_assignment: None,
+            include_stack: vec![],
+            makefile_list: vec![],
+            parse_errors: vec![],
+            scratch_dir: OnceLock::new(),
+            jobserver: OnceLock::new(),
+            vpath_cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Under `opts.require_rules`, fail if the makefile defines no rules.
+    fn check_rules(&mut self) -> Result<(), MakeError> {
+        if self.opts.require_rules && self.rule_map.is_empty() {
+            let e = MakeError::new("Makefile defines no rules.", self.context.clone());
+            self.tolerate(Err(e))?;
+        }
+        Ok(())
+    }
+
+    /// Log all but the last of the errors collected under `opts.tolerant`, and return the last.
+    fn report_parse_errors(mut self) -> Result<Self, MakeError> {
+        let mut errors = std::mem::take(&mut self.parse_errors);
+        match errors.pop() {
+            None => Ok(self),
+            Some(last) => {
+                for e in errors {
+                    self.logger.error(e.msg, Some(&e.context));
+                }
+                Err(last)
+            }
+        }
+    }
+
+    /// Open a makefile and run it through the parser with its own context, restoring the current
+    /// context afterwards. This is used both for the main makefile and for included makefiles. If
+    /// `optional` (e.g., `-include`), then a missing file is silently skipped.
+    fn parse_file(&mut self, path: PathBuf, optional: bool) -> Result<(), MakeError> {
+        // Including a makefile which is already being parsed would recurse forever. Compare
+        // canonical paths where possible, since the same file may be referenced different ways.
+        let canonical = |p: &PathBuf| fs::canonicalize(p).unwrap_or_else(|_| p.clone());
+        let id = canonical(&path);
+        if let Some(i) = self.include_stack.iter().position(|p| canonical(p) == id) {
+            let cycle = self.include_stack[i..]
+                .iter()
+                .chain([&path])
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>();
+            return Err(MakeError::new(
+                format!("Include cycle: {}.", cycle.join(" -> ")),
+                self.context.clone(),
+            ));
+        }
+
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if optional && e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => {
+                return Err(MakeError::new(
+                    format!("Could not read makefile ({}).", e),
+                    self.context.clone(),
+                ))
+            }
         };
 
-        // Open the makefile and run it through the parser.
-        let file = File::open(&path).map_err(|e| {
-            MakeError::new(format!("Could not read makefile ({}).", e), path.into())
-        })?;
-        makefile.parse(BufReader::new(file))?;
+        self.makefile_list.push(path.display().to_string());
+        self.vars
+            .set(
+                "MAKEFILE_LIST".to_string(),
+                self.makefile_list.join(" "),
+                false,
+            )
+            .map_err(|e| MakeError::new(e, self.context.clone()))?;
 
-        Ok(makefile)
+        let parent_context = std::mem::replace(&mut self.context, path.clone().into());
+        self.include_stack.push(path);
+        let result = self.parse(BufReader::new(file));
+        self.include_stack.pop();
+        self.context = parent_context;
+
+        result
     }
 
     /// Iterate over the makefile's lines, call `parse_line` to handle the actual parsing logic, and
     /// manage context.
     fn parse<R: BufRead>(&mut self, stream: R) -> Result<(), MakeError> {
         self.current_rule = None;
+        self.continued_line = None;
+        let conditional_depth = self.conditionals.len();
 
         for (i, result) in stream.lines().enumerate() {
             // Set the context line number and extract the line.
@@ -81,42 +483,177 @@ impl<L: Logger> Makefile<L> {
             self.context.content = Some(line.clone());
 
             // Parse the line.
-            self.parse_line(line)?;
+            let result = self.parse_line(line);
+            self.tolerate(result)?;
+        }
+
+        // A backslash-newline at the end of the file has nothing to continue onto, so warn and drop
+        // the backslash, keeping whatever came before it.
+        let mut trailing_backslash = false;
+        if let Some(last) = self
+            .current_rule
+            .as_mut()
+            .and_then(|r| r.recipe.last_mut())
+            .filter(|last| is_continued(last))
+        {
+            last.pop();
+            last.truncate(last.trim_end().len());
+            trailing_backslash = true;
+        }
+        let continued_line = self.continued_line.take();
+        if trailing_backslash || continued_line.is_some() {
+            self.logger
+                .warn("Backslash-newline at end of file.", Some(&self.context));
+        }
+        if let Some(line) = continued_line {
+            let result = self.parse_line(line);
+            self.tolerate(result)?;
         }
 
-        // Always push two blank lines at the end to terminate trailing rules, even if the last rule
-        // contained a trailing backslash.
-        self.parse_line("".to_string())?;
-        self.parse_line("".to_string())?;
+        // Push a blank line at the end to terminate any trailing rule.
+        let result = self.parse_line("".to_string());
+        self.tolerate(result)?;
+
+        // Conditionals must be closed in the same file they were opened.
+        if self.conditionals.len() > conditional_depth {
+            let conditional = self.conditionals.pop().unwrap();
+            self.conditionals.truncate(conditional_depth);
+            return self.tolerate(Err(MakeError::new("Missing 'endif'.", conditional.context)));
+        }
 
         Ok(())
     }
 
+    /// Under `opts.tolerant`, collect a parse error and carry on with the next line, rather than
+    /// stopping the parse.
+    fn tolerate(&mut self, result: Result<(), MakeError>) -> Result<(), MakeError> {
+        match result {
+            Err(e) if self.opts.tolerant => {
+                self.parse_errors.push(e);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
     /// The line parser is where the "meat" of the parsing occurs. This is responsible for
     /// extracting rules from the physical lines of the makefile stream, properly handling escaped
     /// newlines and semicolons, and also managing state, such as variable assignments and
     /// annotating when the parser moves in-to and out-of a rule definition.
     fn parse_line(&mut self, line: String) -> Result<(), MakeError> {
+        let recipe_prefix = self.vars.get(".RECIPEPREFIX").value.clone();
+
+        // Recipe lines ending in a backslash are continued onto the next line, which is kept as part
+        // of the same command (minus any recipe prefix) so the shell sees the continuation.
+        if let Some(last) = self
+            .current_rule
+            .as_mut()
+            .and_then(|r| r.recipe.last_mut())
+            .filter(|last| is_continued(last))
+        {
+            last.push('\n');
+            last.push_str(line.strip_prefix(&recipe_prefix).unwrap_or(&line));
+            return Ok(());
+        }
+
+        // Outside of recipes, a backslash-newline (and surrounding whitespace) is consumed by make,
+        // joining the physical lines into a single logical line with a single space.
+        let (line, joined) = match self.continued_line.take() {
+            None => (line, false),
+            Some(prev) if line.trim().is_empty() => (prev, true),
+            Some(prev) => (format!("{} {}", prev, line.trim_start()), true),
+        };
+        let is_recipe = !joined && line.starts_with(&recipe_prefix);
+        if !is_recipe && is_continued(&line) {
+            let prev = line[..line.len() - 1].trim_end().to_string();
+            self.continued_line = Some(prev);
+            return Ok(());
+        }
+
+        // Comments are stripped from the whole logical line (so a comment can be continued), except
+        // on recipe lines, which are passed to the shell as they are.
+        let line = if is_recipe {
+            line
+        } else {
+            strip_comment(&line).into_owned()
+        };
+
+        // Errors point at columns of the logical line, so show that line rather than the physical
+        // one if they differ by more than a stripped comment (e.g., for a continued line).
+        if !is_recipe
+            && !self
+                .context
+                .content
+                .as_deref()
+                .is_some_and(|c| c.starts_with(&line))
+        {
+            self.context.content = Some(line.clone());
+        }
+
+        // Conditional directives are handled even within rules, and lines in branches which are not
+        // taken are skipped entirely. As in GNU make, a directive which starts with the recipe
+        // prefix is a recipe line, so it is passed to the shell rather than evaluated.
+        if !is_recipe && self.parse_conditional(line.trim_start(), &line)? {
+            return Ok(());
+        }
+        if self.conditionals.last().is_some_and(|c| !c.active) {
+            return Ok(());
+        }
+
+        // A tab-indented line within a rule was probably meant as a recipe line, but the recipe
+        // prefix has been changed.
+        if !is_recipe
+            && self.current_rule.is_some()
+            && recipe_prefix != "\t"
+            && line.starts_with('\t')
+        {
+            self.logger.warn(
+                format!(
+                    "Line is indented with a tab, which doesn't match the recipe prefix '{}'.",
+                    recipe_prefix
+                ),
+                Some(&self.context),
+            );
+        }
+
         // Handle recipe lines.
-        let recipe_prefix = &self.vars.get(".RECIPEPREFIX").value;
-        if line.starts_with(recipe_prefix) {
+        if is_recipe {
             // If line starts with the recipe prefix, then push it to the current rule.
             match &mut self.current_rule {
-                None => return Err(MakeError::new("recipe without rule", self.context.clone())),
+                None => {
+                    // Point at the start of the recipe text, after the recipe prefix.
+                    let text = line[recipe_prefix.len()..].trim_start();
+                    let mut context = self.context.clone();
+                    context.column_index = Some(line[..line.len() - text.len()].chars().count());
+
+                    // Variable assignments end any rule, so a recipe cannot follow one.
+                    if let Some(previous) = &self.previous_assignment {
+                        self.logger.warn(
+                            "This line is a variable assignment, not a rule.",
+                            Some(previous),
+                        );
+                        return Err(MakeError::new(
+                            "Recipe without rule (the preceding line is a variable assignment).",
+                            context,
+                        ));
+                    }
+
+                    return Err(MakeError::new(
+                        "Recipe commences before first target.",
+                        context,
+                    ));
+                }
                 Some(r) => {
-                    // Strip the recipe prefix first.
+                    // Strip the recipe prefix first. Recipe lines are stored unexpanded, since they
+                    // may reference automatic variables which are only known during execution.
+                    // Blank lines are kept, since they are meaningful under `.ONESHELL`.
                     let cmd = line
-                        .strip_prefix(recipe_prefix)
+                        .strip_prefix(&recipe_prefix)
                         .expect("line known to start with a recipe prefix")
                         .trim()
                         .to_string();
 
-                    if !cmd.is_empty() {
-                        r.recipe.push(
-                            expand(cmd.as_str(), &self.vars)
-                                .map_err(|e| MakeError::new(e, self.context.clone()))?,
-                        );
-                    }
+                    r.recipe.push(cmd);
                 }
             }
             return Ok(());
@@ -127,25 +664,118 @@ impl<L: Logger> Makefile<L> {
             // If there is no default target, see if we can assign one.
             if self.default_target.is_none() {
                 for target in rule.targets.iter() {
-                    // Set default target if none is specified and this is a normal target.
-                    if self.default_target.is_none() && !target.starts_with('.') {
+                    // Set default target if none is specified and this is a normal target (not a
+                    // special target or a pattern).
+                    if self.default_target.is_none()
+                        && !target.starts_with('.')
+                        && !target.contains('%')
+                    {
                         self.default_target = Some(target.clone());
                     }
                 }
             }
 
             // Add the rule to the `rule_map`.
-            self.rule_map.insert(rule, &self.logger)?;
+            self.rule_map.insert(rule, self.logger.as_ref())?;
+        }
+
+        // Leading whitespace is ignored on lines other than recipe lines (which were handled above,
+        // so a leading recipe prefix still means a recipe).
+        let logical = line.as_str();
+        let line = logical.trim_start();
+        self.previous_assignment = None;
+
+        // Ignore blank lines (including lines which were only a comment).
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        // Handle BSD `.include` directives, whose file is quoted (e.g., `.include "a.mk"`) or
+        // bracketed (e.g., `.include <a.mk>`). As with `include`, only the plain form is required.
+        if let Some((name, file)) = bsd_directive(line).filter(|(n, _)| n.ends_with("include")) {
+            self.require_bsd(name)?;
+            let file = ["\"\"", "<>"]
+                .iter()
+                .find_map(|d| {
+                    let (open, close) = d.split_at(1);
+                    file.strip_prefix(open)?.strip_suffix(close)
+                })
+                .unwrap_or(file);
+            let file = self.expand_in(file, logical)?;
+            return self.parse_file(PathBuf::from(file.trim()), name != "include");
         }
 
-        // Ignore pure comments and blank lines.
-        let trimmed_line = line.trim();
-        if trimmed_line.starts_with(COMMENT_INDICATOR) || trimmed_line.is_empty() {
+        // Handle `include` directives. With `-include` (or `sinclude`), missing files are ignored.
+        let include = directive(line, "include")
+            .map(|files| (files, false))
+            .or_else(|| directive(line, "-include").map(|files| (files, true)))
+            .or_else(|| directive(line, "sinclude").map(|files| (files, true)));
+        if let Some((files, optional)) = include {
+            let files = self.expand_in(files, logical)?;
+            for file in files.split_whitespace() {
+                self.parse_file(PathBuf::from(file), optional)?;
+            }
             return Ok(());
         }
 
+        // Handle `export` directives, which either name variables to export (e.g., `export A B`),
+        // define a variable and export it (e.g., `export A = 1`), or with no variables, export
+        // every variable defined in a makefile.
+        if let Some(rest) = directive(line, "export") {
+            if let Some((k, op, v)) = split_assignment(rest) {
+                self.parse_assignment(k, op, v, logical)?;
+                self.unexported.remove(k.trim());
+                self.exported.insert(k.trim().to_string());
+                return Ok(());
+            }
+
+            let names = self.expand_in(rest, logical)?;
+            if names.trim().is_empty() {
+                self.export_all = true;
+            }
+            for name in names.split_whitespace() {
+                self.unexported.remove(name);
+                self.exported.insert(name.to_string());
+            }
+            return Ok(());
+        }
+
+        // Handle `unexport` directives, which are the reverse of `export` (a bare `unexport` undoes
+        // a bare `export`).
+        if let Some(rest) = directive(line, "unexport") {
+            let names = self.expand_in(rest, logical)?;
+            if names.trim().is_empty() {
+                self.export_all = false;
+            }
+            for name in names.split_whitespace() {
+                self.exported.remove(name);
+                self.unexported.insert(name.to_string());
+            }
+            return Ok(());
+        }
+
+        // Handle `undefine` directives, which may be preceded by `override`.
+        let undefine = directive(line, "undefine")
+            .or_else(|| directive(line, "override").and_then(|r| directive(r, "undefine")));
+        if let Some(name) = undefine {
+            let name = self.expand_in(name, logical)?;
+            Vars::validate_name(name.trim())
+                .map_err(|e| MakeError::new(e, self.context.clone()))?;
+            self.vars.unset(name);
+            return Ok(());
+        }
+
+        // Handle variable assignments. These are checked before rules, since variable values may
+        // contain colons (e.g., `PATHS = a:b`), and some operators contain colons (e.g., `:=`).
+        if let Some((k, op, v)) = split_assignment(line) {
+            self.previous_assignment = Some(self.context.clone());
+            return self.parse_assignment(k, op, v, logical);
+        }
+
         // Handle rule definitions.
-        if let Some((targets, mut deps)) = line.split_once(':') {
+        if line.contains(':') {
+            let (targets, mut deps) = line.split_once(':').expect("line known to contain a colon");
+
             // First, if deps start with another `:`, then this is a double-colon rule, so we should
             // mark it as such.
             let mut double_colon = false;
@@ -156,6 +786,18 @@ impl<L: Logger> Makefile<L> {
                 }
             }
 
+            // Handle target-specific variable assignments (e.g., `target: VAR = value`). Since
+            // prerequisites may legitimately contain `=` (e.g., `target: a=b`), a plain `=` is only
+            // considered an assignment if it is separated from the name or value by whitespace.
+            if let Some((k, op, v)) = split_assignment(deps) {
+                let name = k.trim();
+                let spaced = k.ends_with(char::is_whitespace) || v.starts_with(char::is_whitespace);
+                if !name.is_empty() && !name.contains(char::is_whitespace) && (op != "=" || spaced)
+                {
+                    return self.parse_target_var(targets, name, op, v, logical);
+                }
+            }
+
             // There could be a semicolon after prerequisites, in which case we should parse
             // everything after that as a rule line.
             let rule = deps.split_once(';').map(|(d, r)| {
@@ -163,14 +805,21 @@ impl<L: Logger> Makefile<L> {
                 r
             });
 
+            self.warn_automatic_var(targets);
+            self.warn_automatic_var(deps);
+
+            // Prerequisites after a `|` are order-only prerequisites.
+            let deps = self.expand_in(deps, logical)?;
+            let (deps, order_only) = deps.split_once('|').unwrap_or((&deps, ""));
+
             self.current_rule = Some(Rule {
-                targets: expand(targets, &self.vars)
-                    .map_err(|e| MakeError::new(e, self.context.clone()))?
+                targets: self
+                    .expand_in(targets, logical)?
                     .split_whitespace()
                     .map(|s| s.to_string())
                     .collect(),
-                prerequisites: expand(deps, &self.vars)
-                    .map_err(|e| MakeError::new(e, self.context.clone()))?
+                prerequisites: deps.split_whitespace().map(|s| s.to_string()).collect(),
+                order_only: order_only
                     .split_whitespace()
                     .map(|s| s.to_string())
                     .collect(),
@@ -187,43 +836,822 @@ impl<L: Logger> Makefile<L> {
             return Ok(());
         }
 
-        // Handle variable assignments.
-        if let Some((k, v)) = line.split_once('=') {
-            if let Err(e) = self.vars.set(
-                k,
-                &expand(v.trim_start(), &self.vars)
-                    .map_err(|e| MakeError::new(e, self.context.clone()))?,
-                false,
-            ) {
-                return Err(MakeError::new(e, self.context.clone()));
+        // Otherwise, throw error if line is not recognizable.
+        Err(MakeError::new("Invalid line type.", self.context.clone()))
+    }
+
+    /// The environment for expanding with `vars` (e.g., the makefile's variables, or those along
+    /// with the automatic variables of a rule), which carries the options that affect functions.
+    pub(crate) fn expand_env<'a>(&self, vars: &'a Vars) -> ExpandEnv<'a> {
+        ExpandEnv {
+            vars,
+            wildcard_unsorted: self.opts.wildcard_unsorted,
+        }
+    }
+
+    /// Expand `s`, which is part of the `logical` line being parsed, so that an error may point at
+    /// its column.
+    fn expand_in(&self, s: &str, logical: &str) -> Result<String, MakeError> {
+        let env = self.expand_env(&self.vars);
+        expand_with_context(s, &env, &self.context, offset_in(logical, s))
+    }
+
+    /// Handle a conditional directive (`ifeq`, `ifneq`, `else`, or `endif`, or their BSD
+    /// counterparts such as `.if`), returning whether `line` (part of the `logical` line) was one.
+    fn parse_conditional(&mut self, line: &str, logical: &str) -> Result<bool, MakeError> {
+        if let Some((name, rest)) = bsd_directive(line).filter(|(n, _)| !n.ends_with("include")) {
+            self.require_bsd(name)?;
+            match name {
+                "else" => self.else_conditional(None::<fn(&Self) -> _>)?,
+                "elif" => {
+                    self.else_conditional(Some(|m: &Self| m.evaluate_bsd_condition(rest, logical)))?
+                }
+                "endif" => self.end_conditional()?,
+                "ifndef" => {
+                    self.open_conditional(|m| Ok(!m.evaluate_bsd_condition(rest, logical)?))?
+                }
+                _ => self.open_conditional(|m| m.evaluate_bsd_condition(rest, logical))?,
+            }
+            return Ok(true);
+        }
+
+        if let Some(rest) = directive(line, "else") {
+            // An `else` may be followed by another condition (e.g., `else ifeq (a,b)`).
+            let condition =
+                (!rest.is_empty()).then_some(|m: &Self| m.evaluate_condition(rest, logical));
+            self.else_conditional(condition)?;
+            return Ok(true);
+        }
+
+        if let Some(rest) = directive(line, "endif") {
+            if !rest.is_empty() && !rest.starts_with(COMMENT_INDICATOR) {
+                return Err(MakeError::new(
+                    "Extraneous text after 'endif'.",
+                    self.context.clone(),
+                ));
+            }
+            self.end_conditional()?;
+            return Ok(true);
+        }
+
+        if !CONDITIONAL_DIRECTIVES
+            .iter()
+            .any(|name| directive(line, name).is_some())
+        {
+            return Ok(false);
+        }
+
+        self.open_conditional(|m| m.evaluate_condition(line, logical))?;
+        Ok(true)
+    }
+
+    /// Open a conditional, whose first branch is taken if `condition` is true.
+    fn open_conditional(
+        &mut self,
+        condition: impl FnOnce(&Self) -> Result<bool, MakeError>,
+    ) -> Result<(), MakeError> {
+        // Within a skipped branch, nested conditionals are tracked, but never evaluated or taken.
+        let parent_active = self.conditionals.last().is_none_or(|c| c.active);
+        let active = parent_active && condition(self)?;
+        self.conditionals.push(Conditional {
+            active,
+            taken: active || !parent_active,
+            else_seen: false,
+            context: self.context.clone(),
+        });
+        Ok(())
+    }
+
+    /// Switch to the next branch of the innermost conditional, which is taken if no branch has been
+    /// taken yet and `condition` is true (or there is no condition, for a final `else`).
+    fn else_conditional(
+        &mut self,
+        condition: Option<impl FnOnce(&Self) -> Result<bool, MakeError>>,
+    ) -> Result<(), MakeError> {
+        let Some(conditional) = self.conditionals.last_mut() else {
+            return Err(MakeError::new("Unmatched 'else'.", self.context.clone()));
+        };
+        if conditional.else_seen {
+            return Err(MakeError::new(
+                "Only one 'else' per conditional.",
+                self.context.clone(),
+            ));
+        }
+
+        let (taken, else_seen) = (conditional.taken, condition.is_none());
+        let active = !taken && condition.map_or(Ok(true), |c| c(self))?;
+
+        // This `unwrap()` is safe because we checked the stack is not empty above.
+        let conditional = self.conditionals.last_mut().unwrap();
+        conditional.active = active;
+        conditional.taken |= active;
+        conditional.else_seen = else_seen;
+        Ok(())
+    }
+
+    /// Close the innermost conditional.
+    fn end_conditional(&mut self) -> Result<(), MakeError> {
+        if self.conditionals.pop().is_none() {
+            return Err(MakeError::new("Unmatched 'endif'.", self.context.clone()));
+        }
+        Ok(())
+    }
+
+    /// Ensure the BSD directive `.name` is allowed, which is only in the BSD dialect.
+    fn require_bsd(&self, name: &str) -> Result<(), MakeError> {
+        if self.dialect != Dialect::Bsd {
+            return Err(MakeError::new(
+                format!("BSD directive '.{name}' is only supported with `--dialect=bsd`."),
+                self.context.clone(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Evaluate the expression of a BSD conditional such as `.if ${A} == b && defined(B)`. As in BSD
+    /// make, `&&` binds more tightly than `||`, and a bare word `w` is short for `defined(w)`.
+    fn evaluate_bsd_condition(&self, expr: &str, logical: &str) -> Result<bool, MakeError> {
+        for any in split_logical(expr, "||") {
+            let mut all = true;
+            for term in split_logical(any, "&&") {
+                if !self.evaluate_bsd_term(term, logical)? {
+                    all = false;
+                    break;
+                }
+            }
+            if all {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Evaluate a single term of a BSD conditional expression (i.e., without `||` or `&&`).
+    fn evaluate_bsd_term(&self, term: &str, logical: &str) -> Result<bool, MakeError> {
+        let expand = |s: &str| {
+            let s = s
+                .strip_prefix('"')
+                .and_then(|s| s.strip_suffix('"'))
+                .unwrap_or(s);
+            self.expand_in(s, logical)
+        };
+
+        if term.is_empty() {
+            return Err(MakeError::new(
+                "Invalid conditional expression.",
+                self.context.clone(),
+            ));
+        }
+        if let Some(term) = term.strip_prefix('!') {
+            return Ok(!self.evaluate_bsd_term(term.trim_start(), logical)?);
+        }
+        if let Some(inner) = term.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+            return self.evaluate_bsd_condition(inner, logical);
+        }
+
+        if let Some(name) = bsd_function(term, "defined") {
+            return Ok(self.vars.lookup(expand(name)?).is_some());
+        }
+        if let Some(name) = bsd_function(term, "empty") {
+            return Ok(expand(&format!("$({name})"))?.trim().is_empty());
+        }
+        if let Some(target) = bsd_function(term, "target") {
+            return Ok(self.rule_map.has_target(expand(target)?.trim()));
+        }
+        if let Some(file) = bsd_function(term, "exists") {
+            return Ok(Path::new(expand(file)?.trim()).exists());
+        }
+
+        let number = |s: &str| s.trim().parse::<f64>().ok();
+        if let Some((a, op, b)) = split_comparison(term) {
+            let (a, b) = (expand(a)?, expand(b)?);
+            return match (op, number(&a), number(&b)) {
+                (_, Some(a), Some(b)) => Ok(match op {
+                    "==" => a == b,
+                    "!=" => a != b,
+                    "<" => a < b,
+                    ">" => a > b,
+                    "<=" => a <= b,
+                    _ => a >= b,
+                }),
+                ("==", _, _) => Ok(a == b),
+                ("!=", _, _) => Ok(a != b),
+                _ => Err(MakeError::new(
+                    format!("Comparison operator '{op}' requires numbers."),
+                    self.context.clone(),
+                )),
             };
+        }
+
+        // A plain value is true if it is a non-zero number or a non-empty string.
+        if term.starts_with('"') || term.contains('$') || number(term).is_some() {
+            let value = expand(term)?;
+            return Ok(number(&value).map_or(!value.is_empty(), |n| n != 0.0));
+        }
+
+        Ok(self.vars.lookup(term).is_some())
+    }
+
+    /// Evaluate a condition such as `ifeq (a,b)`, `ifneq "a" "b"`, or `ifdef VAR`.
+    fn evaluate_condition(&self, condition: &str, logical: &str) -> Result<bool, MakeError> {
+        // The argument of `ifdef` is the name of the variable to check, but it is expanded, so
+        // `ifdef $(X)` checks the variable named by the value of `X`.
+        let ifdef = directive(condition, "ifdef")
+            .map(|name| (false, name))
+            .or_else(|| directive(condition, "ifndef").map(|name| (true, name)));
+        if let Some((negate, name)) = ifdef {
+            let name = self.expand_in(name, logical)?;
+            let name = name.trim();
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(MakeError::new(
+                    "Invalid variable name in conditional.",
+                    self.context.clone(),
+                ));
+            }
+
+            return Ok(self.vars.is_set(name) != negate);
+        }
+
+        let (negate, args) = if let Some(args) = directive(condition, "ifeq") {
+            (false, args)
+        } else if let Some(args) = directive(condition, "ifneq") {
+            (true, args)
+        } else {
+            return Err(MakeError::new(
+                format!("Invalid conditional '{}'.", condition),
+                self.context.clone(),
+            ));
+        };
+
+        let (a, b) = conditional_args(args).ok_or_else(|| {
+            MakeError::new("Invalid conditional arguments.", self.context.clone())
+        })?;
+        // As in GNU make, only the whitespace around the unexpanded arguments is ignored, so a
+        // value which expands to only whitespace is not equal to the empty string.
+        let a = self.expand_in(a, logical)?;
+        let b = self.expand_in(b, logical)?;
+
+        Ok((a == b) != negate)
+    }
+
+    /// Parse a variable assignment, given the name, operator, and (unexpanded) value, which are
+    /// part of the `logical` line.
+    fn parse_assignment(
+        &mut self,
+        k: &str,
+        op: &str,
+        v: &str,
+        logical: &str,
+    ) -> Result<(), MakeError> {
+        // Conditional assignments only apply if the variable is not already defined.
+        if op == "?=" && self.vars.lookup(k).is_some() {
             return Ok(());
         }
 
-        // Otherwise, throw error if line is not recognizable.
-        Err(MakeError::new("Invalid line type.", self.context.clone()))
+        let v = v.trim_start();
+        let (v, recursive) = match op {
+            // Appended text is expanded now only if the variable is simply-expanded, so recursive
+            // variables keep their recursive semantics.
+            "+=" => {
+                let v = if self.vars.lookup(k).is_none_or(|var| var.recursive) {
+                    v.to_string()
+                } else {
+                    self.warn_automatic_var(v);
+                    self.expand_in(v, logical)?
+                };
+
+                return self
+                    .vars
+                    .append(k, &v)
+                    .map_err(|e| MakeError::new(e, self.context.clone()));
+            }
+
+            // Recursively-expanded variables are stored as written, and expanded each time they are
+            // used.
+            "=" | "?=" => (v.to_string(), true),
+
+            // Simply-expanded variables are expanded exactly once, when they are defined.
+            ":=" | "::=" => {
+                self.warn_automatic_var(v);
+                let v = self.expand_in(v, logical)?;
+                (v, false)
+            }
+
+            // The command is expanded and run now, but (as with GNU make) the output is stored as a
+            // recursively-expanded variable.
+            "!=" => {
+                self.warn_automatic_var(v);
+                let v = run_shell(&self.expand_in(v, logical)?, &self.expand_env(&self.vars))
+                    .map_err(|e| MakeError::new(e, self.context.clone()))?;
+                (v, true)
+            }
+
+            _ => {
+                return Err(MakeError::new(
+                    format!("Unsupported assignment operator '{}'.", op),
+                    self.context.clone(),
+                ));
+            }
+        };
+
+        // The recipe prefix is used to classify lines while parsing, so it must be known now, and
+        // validated after expansion (a blank value resets it to a tab).
+        let (v, recursive) = if k.trim() == ".RECIPEPREFIX" {
+            let v = if recursive {
+                expand_with_context(&v, &self.expand_env(&self.vars), &self.context, None)?
+            } else {
+                v
+            };
+            if v.chars().count() > 1 {
+                return Err(MakeError::new(
+                    ".RECIPEPREFIX must be a single character.",
+                    self.context.clone(),
+                ));
+            }
+            (v, false)
+        } else {
+            (v, recursive)
+        };
+
+        self.vars
+            .set(k, &v, recursive)
+            .map_err(|e| MakeError::new(e, self.context.clone()))
+    }
+
+    /// Parse a target-specific variable assignment. Recursively-expanded values are stored as
+    /// written, so they may reference automatic variables (e.g., `target: OUT = $@.out`).
+    fn parse_target_var(
+        &mut self,
+        targets: &str,
+        k: &str,
+        op: &str,
+        v: &str,
+        logical: &str,
+    ) -> Result<(), MakeError> {
+        Vars::validate_name(k).map_err(|e| MakeError::new(e, self.context.clone()))?;
+
+        let v = v.trim_start();
+        let recursive = matches!(op, "=" | "?=");
+        let v = if recursive {
+            v.to_string()
+        } else {
+            self.warn_automatic_var(v);
+            self.expand_in(v, logical)?
+        };
+
+        for target in self.expand_in(targets, logical)?.split_whitespace() {
+            self.target_vars
+                .entry(target.to_string())
+                .or_default()
+                .push((k.to_string(), v.clone(), recursive));
+        }
+
+        Ok(())
+    }
+
+    /// Automatic variables are only bound while executing recipes, so warn if they are referenced
+    /// in text which is expanded at parse time, where they always expand to empty.
+    fn warn_automatic_var(&self, s: &str) {
+        if let Some(ch) = find_automatic_var(s) {
+            self.logger.warn(
+                format!("Automatic variable `${ch}` is only set within recipes."),
+                Some(&self.context),
+            );
+        }
     }
 
-    /// Principal interface for executing a parsed makefile, given a list of targets.
-    pub fn execute(&self, mut targets: Vec<String>) -> Result<(), MakeError> {
+    /// The explicit targets of the parsed makefile, in the order they were first defined, for
+    /// inspecting a makefile without executing it.
+    ///
+    /// ```
+    /// use omake::{BufferLogger, Makefile, Opts, Vars};
+    ///
+    /// let content: &[u8] = b"all: a b\n\techo all\na:\n\techo a\nb: a\n";
+    /// let logger = Box::new(BufferLogger::default());
+    /// let makefile = Makefile::from_reader(content, Opts::default(), logger, Vars::new([])).unwrap();
+    ///
+    /// assert_eq!(makefile.targets(), vec!["all", "a", "b"]);
+    /// for target in makefile.targets() {
+    ///     for rule in makefile.rules_for(target) {
+    ///         println!("{target}: {:?} {:?}", rule.prerequisites, rule.recipe);
+    ///     }
+    /// }
+    /// assert_eq!(makefile.rules_for("b")[0].prerequisites, vec!["a"]);
+    /// ```
+    pub fn targets(&self) -> Vec<&str> {
+        self.rule_map.targets()
+    }
+
+    /// Get every rule which could make `target` (see [`RuleMap::rules_for`]).
+    pub fn rules_for(&self, target: &str) -> Vec<&Rule> {
+        self.rule_map.rules_for(target)
+    }
+
+    /// Print the variables and rules to stdout in a readable format (for `-p`), e.g., to debug how
+    /// variables were assigned. Write errors are ignored.
+    pub fn print_database(&self) {
+        let _ = self.write_database(&mut io::stdout().lock());
+    }
+
+    /// Write the variables (sorted by name, with their origin, and with `=` or `:=` for their
+    /// flavor) and the rules (explicit rules in the order their targets were defined, followed by
+    /// pattern rules).
+    fn write_database(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "# Variables")?;
+        let mut vars = self.vars.iter().collect::<Vec<_>>();
+        vars.sort_unstable_by_key(|(k, _)| *k);
+        for (k, var) in vars {
+            let op = if var.recursive { "=" } else { ":=" };
+            writeln!(out, "\n# {}\n{k} {op} {}", var.origin.as_str(), var.value)?;
+        }
+
+        writeln!(out, "\n# Rules")?;
+        let explicit = self
+            .targets()
+            .into_iter()
+            .flat_map(|t| self.rule_map.get(t).into_iter().map(move |r| (t, r)));
+        let patterns = self
+            .rule_map
+            .pattern_rules()
+            .into_iter()
+            .map(|r| (r.targets.join(" "), r));
+        for (target, rule) in explicit.map(|(t, r)| (t.to_string(), r)).chain(patterns) {
+            if let Some(label) = rule.context.label() {
+                write!(out, "\n# {label}")?;
+            }
+            let sep = if rule.double_colon { "::" } else { ":" };
+            write!(out, "\n{target}{sep}")?;
+            for prereq in &rule.prerequisites {
+                write!(out, " {prereq}")?;
+            }
+            if !rule.order_only.is_empty() {
+                write!(out, " | {}", rule.order_only.join(" "))?;
+            }
+            writeln!(out)?;
+            for line in &rule.recipe {
+                writeln!(out, "\t{line}")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The target which is made when `execute` is given no targets.
+    pub fn default_goal(&self) -> Option<&str> {
+        self.default_target.as_deref()
+    }
+
+    /// Override the target which is made when `execute` is given no targets. If no rule makes
+    /// `target`, then a warning is logged, but the goal is still set since it could be a file.
+    pub fn set_default_goal(&mut self, target: impl Into<String>) {
+        let target = target.into();
+        if !self.rule_map.has_target(&target) {
+            self.logger
+                .warn(format!("No rule to make default goal '{}'.", target), None);
+        }
+
+        self.default_target = Some(target);
+        self.default_target_explicit = true;
+    }
+
+    /// Principal interface for executing a parsed makefile, given a list of targets. Returns
+    /// whether any target was out of date, which under `question` is the answer to the question.
+    pub fn execute(&self, targets: Vec<String>) -> Result<bool, MakeError>
+    where
+        L: Sync,
+    {
+        let (ctx, result) = self.run(targets);
+        result.map(|()| ctx.out_of_date)
+    }
+
+    /// Execute a parsed makefile, like `execute`, and report which target files were created or
+    /// modified (e.g., so a wrapping build system knows what changed). The changes are returned
+    /// even if making the targets fails, along with the result.
+    pub fn execute_with_changes(
+        &self,
+        targets: Vec<String>,
+    ) -> (Vec<FileChange>, Result<(), MakeError>)
+    where
+        L: Sync,
+    {
+        let (ctx, result) = self.run(targets);
+        (ctx.changes, result)
+    }
+
+    /// Make the targets (or the default goal), returning the state of the run along with the
+    /// result.
+    fn run(&self, mut targets: Vec<String>) -> (ExecContext, Result<(), MakeError>)
+    where
+        L: Sync,
+    {
         // Set targets list to default target if none were provided.
         if targets.is_empty() {
             match &self.default_target {
+                None if self.rule_map.is_empty() => {
+                    let e = MakeError::new(
+                        "No target specified and the makefile defines no rules.",
+                        Context::new(),
+                    );
+                    return (ExecContext::new(), Err(e));
+                }
                 None => {
-                    return Err(MakeError::new(
+                    let e = MakeError::new(
                         "No target specified and no default target found.",
                         Context::new(),
-                    ))
+                    );
+                    return (ExecContext::new(), Err(e));
+                }
+                Some(t) => {
+                    if self.opts.debug {
+                        let reason = if self.default_target_explicit {
+                            "set explicitly"
+                        } else {
+                            "the first normal target"
+                        };
+                        self.logger
+                            .info(format!("Using default goal '{t}' ({reason})."), None);
+                    }
+                    targets.push(t.clone());
                 }
-                Some(t) => targets.push(t.clone()),
             }
         }
 
-        for target in targets {
-            self.rule_map.execute(self, &target)?;
+        // Under `keep_going`, continue with the other targets if one fails. The last error is
+        // returned, and any others are logged.
+        let mut ctx = ExecContext::new();
+        let jobserver = self.jobserver();
+        if jobserver.is_some() {
+            ctx.plan = Some(Default::default());
         }
 
-        Ok(())
+        let mut errors = vec![];
+        let mut unmade = vec![];
+        for target in &targets {
+            if let Err(e) = self.rule_map.execute(self, target, &mut ctx) {
+                if !self.opts.keep_going {
+                    self.remove_intermediates(&mut ctx);
+                    return (ctx, Err(e));
+                }
+                errors.push(e);
+                unmade.push(target);
+            }
+        }
+
+        // Under `-j`, the recipes are only run once all of the goals have been planned. Goals
+        // which were left unmade get the same error as when keeping going in a serial run.
+        if let (Some(jobserver), Some(plan)) = (jobserver, ctx.plan.take()) {
+            if let Err(e) = parallel::run(self, plan, jobserver, &mut ctx) {
+                self.remove_intermediates(&mut ctx);
+                return (ctx, Err(e));
+            }
+            for target in &targets {
+                let left = ctx.failed.contains(target) || ctx.skipped.contains(target);
+                if left && !unmade.contains(&target) {
+                    errors.push(RuleMap::not_remade(target));
+                }
+            }
+        }
+
+        self.remove_intermediates(&mut ctx);
+
+        match errors.pop() {
+            None => (ctx, Ok(())),
+            Some(last) => {
+                for e in errors {
+                    self.logger.error(e.msg, Some(&e.context));
+                }
+                self.log_keep_going_summary(&ctx);
+                (ctx, Err(last))
+            }
+        }
+    }
+
+    /// Remove the `.INTERMEDIATE` files which were created during this run. Files which already
+    /// existed before the run are kept.
+    fn remove_intermediates(&self, ctx: &mut ExecContext) {
+        ctx.changes.retain(|change| {
+            let FileChange::Created(file) = change else {
+                return true;
+            };
+            if !self.rule_map.is_intermediate(file) {
+                return true;
+            }
+
+            self.logger
+                .info(format!("Removing intermediate file '{file}'."), None);
+            if let Err(e) = fs::remove_file(file) {
+                self.logger.warn(
+                    format!("Could not remove intermediate file '{file}' ({e})."),
+                    None,
+                );
+            }
+            false
+        });
+    }
+
+    /// Summarize how many (and which) targets failed, and which were not attempted because a
+    /// prerequisite failed, after keeping going past errors.
+    fn log_keep_going_summary(&self, ctx: &ExecContext) {
+        let listed = |targets: &[String]| {
+            let quoted = targets
+                .iter()
+                .map(|t| format!("'{t}'"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("({}): {}", targets.len(), quoted)
+        };
+
+        if !ctx.failed.is_empty() {
+            self.logger
+                .info(format!("Failed targets {}.", listed(&ctx.failed)), None);
+        }
+        if !ctx.skipped.is_empty() {
+            self.logger.info(
+                format!(
+                    "Targets not attempted because of failed prerequisites {}.",
+                    listed(&ctx.skipped)
+                ),
+                None,
+            );
+        }
+    }
+
+    /// Determine which files involved in making `goal` are out of date, along with their current
+    /// `mtime`s, without executing anything (e.g., so an IDE can show what is stale).
+    pub fn prerequisites_closure_mtimes(&self, goal: &str) -> HashMap<String, Freshness> {
+        let mut ctx = ExecContext::new();
+        let mut freshness = HashMap::new();
+        self.rule_map
+            .freshness(self, goal, &mut ctx, &mut freshness);
+        freshness
+    }
+
+    /// Get the path to the scratch directory for this invocation, creating it if needed. The
+    /// directory is removed when the `Makefile` is dropped.
+    pub fn scratch_dir(&self) -> Result<&Path, MakeError> {
+        if let Some(dir) = self.scratch_dir.get() {
+            return Ok(dir.path());
+        }
+
+        let dir = ScratchDir::new().map_err(|e| {
+            MakeError::new(
+                format!("Could not create scratch directory ({}).", e),
+                Context::new(),
+            )
+        })?;
+        Ok(self.scratch_dir.get_or_init(|| dir).path())
+    }
+
+    /// Get the jobserver for running jobs in parallel, which is shared with the parent `make` if
+    /// there is one. There is none for `-j1`, or if it could not be set up, in which case jobs are
+    /// run one at a time.
+    pub(crate) fn jobserver(&self) -> Option<&Jobserver> {
+        self.jobserver
+            .get_or_init(|| {
+                if self.opts.jobs <= 1 {
+                    return None;
+                }
+
+                // The named pipe for the `fifo` style goes in the scratch directory.
+                let jobserver = match (&self.opts.jobserver_auth, self.opts.jobserver_style) {
+                    (Some(auth), _) => Jobserver::from_auth(auth).map_err(|e| e.to_string()),
+                    (None, JobserverStyle::Fifo) => {
+                        self.scratch_dir().map_err(|e| e.msg).and_then(|dir| {
+                            Jobserver::fifo(self.opts.jobs, dir).map_err(|e| e.to_string())
+                        })
+                    }
+                    (None, JobserverStyle::Pipe) => {
+                        Jobserver::pipe(self.opts.jobs).map_err(|e| e.to_string())
+                    }
+                };
+                jobserver
+                    .map_err(|e| {
+                        self.logger
+                            .warn(format!("Jobserver unavailable, using -j1 ({}).", e), None)
+                    })
+                    .ok()
+            })
+            .as_ref()
+    }
+
+    /// The `MAKEFLAGS` passed to recipes, which includes the jobserver (if any) so that sub-makes
+    /// share this job pool.
+    pub(crate) fn child_makeflags(&self) -> String {
+        let flags = &self.vars.get("MAKEFLAGS").value;
+        match self.jobserver.get().and_then(Option::as_ref) {
+            None => flags.clone(),
+            Some(jobserver) => format!(
+                "{} -j{} --jobserver-auth={}",
+                flags,
+                self.opts.jobs,
+                jobserver.auth()
+            )
+            .trim_start()
+            .to_string(),
+        }
+    }
+
+    /// The (expanded) values of the variables exported with `export`, for the environment of
+    /// recipes. As in GNU make, `SHELL` is only passed on if it is exported, so otherwise `$$SHELL`
+    /// in a recipe is the environment's value, even if the makefile runs recipes with another.
+    /// After a bare `export`, this includes every variable defined in a makefile whose name is
+    /// valid in the environment.
+    pub(crate) fn exported_env(&self) -> Result<Vec<(String, String)>, String> {
+        let mut names = self
+            .exported
+            .iter()
+            .map(String::as_str)
+            .collect::<HashSet<_>>();
+        if self.export_all {
+            names.extend(
+                self.vars
+                    .iter()
+                    .filter(|(k, var)| var.origin == Origin::File && is_env_name(k))
+                    .map(|(k, _)| k)
+                    .filter(|k| !self.unexported.contains(*k)),
+            );
+        }
+
+        names
+            .into_iter()
+            .filter_map(|k| self.vars.lookup(k).map(|var| (k, var)))
+            .map(|(k, var)| {
+                let value = if var.recursive {
+                    self.expand_env(&self.vars).expand(&var.value)?
+                } else {
+                    var.value.clone()
+                };
+                Ok((k.to_string(), value))
+            })
+            .collect()
+    }
+
+    /// The variables removed from the environment of recipes with `unexport`.
+    pub(crate) fn unexported_env(&self) -> impl Iterator<Item = &str> {
+        self.unexported.iter().map(String::as_str)
+    }
+
+    /// Log a message about searching for rules and files, under `--debug=verbose`.
+    pub(crate) fn debug_verbose(&self, msg: impl AsRef<str>) {
+        if self.opts.debug_verbose {
+            self.logger.info(msg, None);
+        }
+    }
+
+    /// Find `file`, which is `file` itself if it exists, or else its path within the first of the
+    /// directories listed in `VPATH` (separated by colons or whitespace) which contains it. The
+    /// search of the `VPATH` directories is cached.
+    pub(crate) fn vpath_search(&self, file: &str) -> Option<String> {
+        if Path::new(file).exists() {
+            return Some(file.to_string());
+        }
+        if Path::new(file).is_absolute() {
+            return None;
+        }
+
+        let cached = self
+            .vpath_cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(file)
+            .cloned();
+        if let Some(found) = cached {
+            return found;
+        }
+
+        let found = self.search_vpath_dirs(file);
+        self.vpath_cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(file.to_string(), found.clone());
+        found
+    }
+
+    /// Find `file` within the first of the `VPATH` directories which contains it.
+    fn search_vpath_dirs(&self, file: &str) -> Option<String> {
+        let vpath = self.vars.get("VPATH");
+        let dirs = if vpath.recursive {
+            self.expand_env(&self.vars).expand(&vpath.value).ok()?
+        } else {
+            vpath.value.clone()
+        };
+
+        for dir in dirs.split(|c: char| c == ':' || c.is_whitespace()) {
+            if dir.is_empty() {
+                continue;
+            }
+
+            self.debug_verbose(format!("Looking for '{file}' in VPATH directory '{dir}'."));
+            let path = Path::new(dir).join(file);
+            if path.exists() {
+                let path = path.to_string_lossy().into_owned();
+                self.debug_verbose(format!("Found '{file}' as '{path}'."));
+                return Some(path);
+            }
+        }
+
+        None
     }
 
     /// Get the `mtime` of a file. Note that the return value also signals whether or not the file
@@ -232,7 +1660,7 @@ impl<L: Logger> Makefile<L> {
     ///
     /// TODO: Consider bailing on a file permissions issue? Not sure if POSIX specifies some
     /// behavior here or if the major implementations halt execution on a permissions error.
-    fn get_mtime(&self, file: &String) -> Option<SystemTime> {
+    pub(crate) fn get_mtime(&self, file: &String) -> Option<SystemTime> {
         match fs::metadata(file) {
             Ok(metadata) => {
                 if self.opts.old_file.contains(file) {
@@ -248,3 +1676,686 @@ impl<L: Logger> Makefile<L> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::expand::expand;
+    use crate::logger::DefaultLogger;
+
+    /// Parse a makefile from `content`, without writing it to a file.
+    fn parse_makefile(content: &str) -> Makefile<DefaultLogger> {
+        Makefile::from_reader(
+            content.as_bytes(),
+            Opts::default(),
+            Box::new(DefaultLogger {}),
+            Vars::new([]),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_split_assignment() {
+        assert_eq!(split_assignment("VAR := x"), Some(("VAR ", ":=", " x")));
+        assert_eq!(split_assignment("VAR::=x"), Some(("VAR", "::=", "x")));
+        assert_eq!(split_assignment("VAR = a:b"), Some(("VAR ", "=", " a:b")));
+        assert_eq!(split_assignment("VAR ?= x=y"), Some(("VAR ", "?=", " x=y")));
+        assert_eq!(split_assignment("target: VAR = x"), None);
+        assert_eq!(split_assignment("target: a=b"), None);
+        assert_eq!(split_assignment("target: a"), None);
+    }
+
+    #[test]
+    fn test_assignment_operator_key() {
+        let makefile = parse_makefile("VAR := x\nVAR2 ::= y\n");
+        assert_eq!(makefile.vars.get("VAR").value, "x");
+        assert_eq!(makefile.vars.get("VAR2").value, "y");
+        assert_eq!(makefile.vars.get("VAR :").origin, Origin::Undefined);
+    }
+
+    #[test]
+    fn test_simple_and_recursive_assignment() {
+        let makefile = parse_makefile("B = 1\nA := $(B)\nC = $(B)\nB = 2\n");
+        assert_eq!(makefile.vars.get("A").value, "1");
+        assert!(!makefile.vars.get("A").recursive);
+        assert_eq!(makefile.vars.get("C").value, "$(B)");
+        assert!(makefile.vars.get("C").recursive);
+        assert_eq!(expand("$(A) $(C)", &makefile.vars).unwrap(), "1 2");
+    }
+
+    #[test]
+    fn test_append_assignment() {
+        let makefile = parse_makefile("A += a\nB = b\nB += $(X)\nC := c\nC += $(X)\nX = x\n");
+        assert_eq!(expand("[$(A)]", &makefile.vars).unwrap(), "[a]");
+        assert_eq!(expand("$(B)", &makefile.vars).unwrap(), "b x");
+        assert_eq!(expand("[$(C)]", &makefile.vars).unwrap(), "[c ]");
+    }
+
+    #[test]
+    fn test_conditional_args() {
+        assert_eq!(conditional_args("(a,b)"), Some(("a", "b")));
+        assert_eq!(
+            conditional_args(" ( a , $(f x,y) ) "),
+            Some(("a", "$(f x,y)"))
+        );
+        assert_eq!(conditional_args("(,)"), Some(("", "")));
+        assert_eq!(conditional_args("\"a b\" 'c'"), Some(("a b", "c")));
+        assert_eq!(conditional_args("\"a\"'b'"), Some(("a", "b")));
+        assert_eq!(conditional_args("(a)"), None);
+        assert_eq!(conditional_args("\"a\""), None);
+        assert_eq!(conditional_args("\"a\" \"b\" c"), None);
+    }
+
+    #[test]
+    fn test_is_continued() {
+        assert!(is_continued("a \\"));
+        assert!(!is_continued("a \\\\"));
+        assert!(is_continued("a \\\\\\"));
+        assert!(!is_continued("a"));
+    }
+
+    #[test]
+    fn test_strip_comment() {
+        assert_eq!(strip_comment("a: b # build a"), "a: b ");
+        assert_eq!(strip_comment("A = 1# one"), "A = 1");
+        assert_eq!(strip_comment("# only a comment"), "");
+        assert_eq!(strip_comment(r"A = a\#b # c"), "A = a#b ");
+        assert_eq!(
+            strip_comment("A = $(subst #,-,$(B)) # c"),
+            "A = $(subst #,-,$(B)) "
+        );
+        assert_eq!(strip_comment("A = $$# c"), "A = $$");
+        assert_eq!(strip_comment("a: b; echo '#' # c"), "a: b; echo '#' # c");
+        assert_eq!(strip_comment("A = a;b # c"), "A = a;b ");
+        assert_eq!(strip_comment("a: b"), "a: b");
+    }
+
+    #[test]
+    fn test_set_default_goal() {
+        let scratch = ScratchDir::new().unwrap();
+        let out = scratch.path().join("out");
+        let mut makefile = parse_makefile(&format!(
+            "a:\n\techo a\n\nb:\n\techo b > {}\n",
+            out.display()
+        ));
+        assert_eq!(makefile.default_goal(), Some("a"));
+
+        makefile.set_default_goal("b");
+        assert_eq!(makefile.default_goal(), Some("b"));
+        makefile.execute(vec![]).unwrap();
+        assert_eq!(fs::read_to_string(&out).unwrap(), "b\n");
+    }
+
+    #[test]
+    fn test_execute_prereq_chain() {
+        let scratch = ScratchDir::new().unwrap();
+        let out = scratch.path().join("out");
+        let out = out.display();
+        let makefile = parse_makefile(&format!(
+            "all: a b\n\techo all >> {out}\na: b\n\techo a >> {out}\nb:\n\techo b >> {out}\n"
+        ));
+
+        makefile.execute(vec![]).unwrap();
+        assert_eq!(fs::read_to_string(out.to_string()).unwrap(), "b\na\nall\n");
+    }
+
+    #[test]
+    fn test_execute_diamond() {
+        let scratch = ScratchDir::new().unwrap();
+        let out = scratch.path().join("out");
+        let out = out.display();
+        let makefile = parse_makefile(&format!(
+            "all: left right\n\techo all >> {out}\n\
+                 left: shared\n\techo left >> {out}\n\
+                 right: shared\n\techo right >> {out}\n\
+                 shared:\n\techo shared >> {out}\n"
+        ));
+
+        // The shared prerequisite is made once, though both of its dependents need it.
+        makefile.execute(vec![]).unwrap();
+        assert_eq!(
+            fs::read_to_string(out.to_string()).unwrap(),
+            "shared\nleft\nright\nall\n"
+        );
+    }
+
+    #[test]
+    fn test_execute_with_changes() {
+        let scratch = ScratchDir::new().unwrap();
+        let dir = scratch.path();
+        let makefile = parse_makefile(&format!(
+            "{dir}/new:\n\ttouch $@\n{dir}/old: {dir}/src\n\ttouch $@\n",
+            dir = dir.display()
+        ));
+        let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+        // The old file exists but is out of date, since its prereq is newer.
+        fs::write(file("src"), "").unwrap();
+        fs::write(file("old"), "").unwrap();
+        File::options()
+            .write(true)
+            .open(file("old"))
+            .unwrap()
+            .set_modified(UNIX_EPOCH)
+            .unwrap();
+
+        let goals = vec![file("new"), file("old")];
+        let (changes, result) = makefile.execute_with_changes(goals.clone());
+        assert!(result.is_ok());
+        assert_eq!(
+            changes,
+            vec![
+                FileChange::Created(file("new")),
+                FileChange::Modified(file("old"))
+            ]
+        );
+        assert_eq!(makefile.execute_with_changes(goals).0, vec![]);
+    }
+
+    #[test]
+    fn test_execute_with_changes_error() {
+        let scratch = ScratchDir::new().unwrap();
+        let dir = scratch.path();
+        let makefile = parse_makefile(&format!(
+            "{dir}/new:\n\ttouch $@\n{dir}/fail:\n\tfalse\n",
+            dir = dir.display()
+        ));
+        let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+        // The changes made before the failure are returned with the error.
+        let (changes, result) = makefile.execute_with_changes(vec![file("new"), file("fail")]);
+        assert!(result.is_err());
+        assert_eq!(changes, vec![FileChange::Created(file("new"))]);
+    }
+
+    #[test]
+    fn test_execute_deep_chain() {
+        // Each target depends on the next, which would overflow the stack if the dependency graph
+        // were traversed recursively.
+        let depth = 10_000;
+        let content = (0..depth)
+            .map(|i| format!("t{}: t{}\n", i, i + 1))
+            .collect::<String>()
+            + &format!("t{depth}:\n");
+        let makefile = parse_makefile(&content);
+        makefile.execute(vec![]).unwrap();
+    }
+
+    #[test]
+    fn test_execute_equal_mtimes_rebuilds() {
+        let scratch = ScratchDir::new().unwrap();
+        let dir = scratch.path();
+        let makefile = parse_makefile(&format!(
+            "{dir}/target: {dir}/prereq\n\techo rebuilt > {dir}/target\n",
+            dir = dir.display()
+        ));
+
+        // Give the prereq the exact same (sub-second) `mtime` as the target.
+        let mtime = SystemTime::now();
+        for name in ["target", "prereq"] {
+            let file = File::create(dir.join(name)).unwrap();
+            file.set_modified(mtime).unwrap();
+        }
+
+        makefile.execute(vec![]).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("target")).unwrap(), "rebuilt\n");
+
+        // Once the target is newer, it is up to date.
+        let file = File::options()
+            .write(true)
+            .open(dir.join("prereq"))
+            .unwrap();
+        file.set_modified(mtime - Duration::from_nanos(1)).unwrap();
+        fs::write(dir.join("target"), "").unwrap();
+        File::options()
+            .write(true)
+            .open(dir.join("target"))
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        makefile.execute(vec![]).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("target")).unwrap(), "");
+    }
+
+    #[test]
+    fn test_execute_double_colon_freshness() {
+        let scratch = ScratchDir::new().unwrap();
+        let dir = scratch.path();
+        let makefile = parse_makefile(&format!(
+            "{dir}/target:: {dir}/old\n\techo old >> {dir}/log\n\
+                 {dir}/target:: {dir}/new\n\techo new >> {dir}/log\n\
+                 {dir}/target::\n\techo always >> {dir}/log\n",
+            dir = dir.display()
+        ));
+
+        let set_mtime = |name: &str, mtime: SystemTime| {
+            File::create(dir.join(name))
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+        };
+        let now = SystemTime::now();
+        set_mtime("target", now);
+        set_mtime("old", now - Duration::from_secs(10));
+        set_mtime("new", now + Duration::from_secs(10));
+
+        // Only the rule whose own prereq is newer than the target runs, along with the rule which
+        // has no prereqs.
+        makefile.execute(vec![]).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("log")).unwrap(),
+            "new\nalways\n"
+        );
+    }
+
+    #[test]
+    fn test_execute_low_resolution_time() {
+        let scratch = ScratchDir::new().unwrap();
+        let dir = scratch.path();
+        let rule = format!(
+            "{dir}/target: {dir}/prereq\n\techo rebuilt > {dir}/target\n",
+            dir = dir.display()
+        );
+
+        // The prereq is newer than the target, but only within the same second.
+        let second = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let set_mtimes = || {
+            fs::write(dir.join("target"), "").unwrap();
+            fs::write(dir.join("prereq"), "").unwrap();
+            for (name, mtime) in [
+                ("target", second),
+                ("prereq", second + Duration::from_millis(500)),
+            ] {
+                let file = File::options().write(true).open(dir.join(name)).unwrap();
+                file.set_modified(mtime).unwrap();
+            }
+        };
+
+        let makefile = parse_makefile(&format!(
+            ".LOW_RESOLUTION_TIME: {}/target\n{}",
+            dir.display(),
+            rule
+        ));
+        set_mtimes();
+        makefile.execute(vec![]).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("target")).unwrap(), "");
+
+        let makefile = parse_makefile(&rule);
+        set_mtimes();
+        makefile.execute(vec![]).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("target")).unwrap(), "rebuilt\n");
+    }
+
+    #[test]
+    fn test_scratch_dir() {
+        let makefile = parse_makefile("all:\n\ttrue\n");
+        let dir = makefile.scratch_dir().unwrap().to_path_buf();
+        assert_eq!(makefile.scratch_dir().unwrap(), dir);
+
+        let rsp = dir.join("args.rsp");
+        expand(&format!("$(file >{},-a -b)", rsp.display()), &makefile.vars).unwrap();
+        assert_eq!(fs::read_to_string(&rsp).unwrap(), "-a -b\n");
+
+        drop(makefile);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_prerequisites_closure_mtimes() {
+        let scratch = ScratchDir::new().unwrap();
+        let dir = scratch.path();
+        let makefile = parse_makefile(&format!(
+            "{dir}/out: {dir}/fresh\n\ttouch {dir}/out\n{dir}/fresh:\n\ttouch {dir}/fresh\n",
+            dir = dir.display()
+        ));
+        let fresh = dir.join("fresh");
+        fs::write(&fresh, "").unwrap();
+
+        let out = dir.join("out").display().to_string();
+        let freshness = makefile.prerequisites_closure_mtimes(&out);
+        assert_eq!(freshness.len(), 2);
+        assert_eq!(
+            freshness[&out],
+            Freshness {
+                mtime: None,
+                out_of_date: true
+            }
+        );
+        let fresh_freshness = &freshness[&fresh.display().to_string()];
+        assert!(fresh_freshness.mtime.is_some());
+        assert!(!fresh_freshness.out_of_date);
+
+        // Nothing was executed.
+        assert!(!dir.join("out").exists());
+    }
+
+    #[test]
+    fn test_prerequisites_closure_mtimes_existing_prereq() {
+        // An existing prereq is not remade, even if its own prereq is missing, so only its `mtime`
+        // is compared to the target (as when executing).
+        let scratch = ScratchDir::new().unwrap();
+        let dir = scratch.path();
+        let makefile = parse_makefile(&format!(
+            "{dir}/a: {dir}/b\n\ttouch {dir}/a\n{dir}/b: {dir}/c\n\ttouch {dir}/b\n\
+             {dir}/c:\n\ttouch {dir}/c\n",
+            dir = dir.display()
+        ));
+        let (a, b, c) = (dir.join("a"), dir.join("b"), dir.join("c"));
+        File::create(&b).unwrap().set_modified(UNIX_EPOCH).unwrap();
+        fs::write(&a, "").unwrap();
+
+        let a = a.display().to_string();
+        let freshness = makefile.prerequisites_closure_mtimes(&a);
+        assert_eq!(freshness.len(), 2);
+        assert!(!freshness[&a].out_of_date);
+        assert!(!freshness[&b.display().to_string()].out_of_date);
+        assert!(!freshness.contains_key(&c.display().to_string()));
+
+        makefile.execute(vec![a]).unwrap();
+        assert!(!c.exists());
+    }
+
+    #[test]
+    fn test_prerequisites_closure_mtimes_deep_chain() {
+        // A long chain of prerequisites (ending in a circular dependency) doesn't overflow the
+        // stack, since it isn't walked recursively.
+        let mut content = (0..10_000)
+            .map(|i| format!("t{}: t{}\n", i, i + 1))
+            .collect::<String>();
+        content.push_str("t10000: t0\n");
+        let makefile = parse_makefile(&content);
+
+        let freshness = makefile.prerequisites_closure_mtimes("t0");
+        assert_eq!(freshness.len(), 10_001);
+        assert!(freshness.values().all(|f| f.out_of_date));
+    }
+
+    #[test]
+    fn test_recipe_transform() {
+        // Use a shell script which records the command it receives as its first argument.
+        let scratch = ScratchDir::new().unwrap();
+        let dir = scratch.path();
+        let script = dir.join("record.sh");
+        let received = dir.join("received");
+        fs::write(
+            &script,
+            format!("printf '%s' \"$1\" > {}\n", received.display()),
+        )
+        .unwrap();
+
+        let mut makefile = parse_makefile(&format!(
+            ".SHELLFLAGS = {}\nall:\n\techo hi\n",
+            script.display()
+        ));
+        makefile.recipe_transform = Some(|command| command.to_uppercase());
+        makefile.execute(vec![]).unwrap();
+        assert_eq!(fs::read_to_string(&received).unwrap(), "ECHO HI");
+    }
+
+    #[test]
+    fn test_execute_recursive_shell() {
+        let makefile = parse_makefile(
+            "SH = /bin/sh\nSHELL = $(SH)\nFLAGS = -c\n.SHELLFLAGS = $(FLAGS)\nall:\n\ttrue\n",
+        );
+        makefile.execute(vec![]).unwrap();
+    }
+
+    #[test]
+    fn test_execute_circular_dependency() {
+        let makefile = parse_makefile("a: b\n\ttrue\nb: a\n\ttrue\n");
+        makefile.execute(vec![]).unwrap();
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let content: &[u8] = b"X = 1\nall: a b\n\techo $(X)\na:\n\ttrue\nb: a\n";
+        let makefile = Makefile::from_reader(
+            content,
+            Opts::default(),
+            Box::new(BufferLogger::default()),
+            Vars::new([]),
+        )
+        .unwrap();
+        assert_eq!(makefile.default_goal(), Some("all"));
+        assert_eq!(makefile.rule_map.get("all")[0].recipe, vec!["echo $(X)"]);
+        assert_eq!(makefile.rule_map.get("b")[0].prerequisites, vec!["a"]);
+        assert_eq!(makefile.vars.get("X").value, "1");
+
+        let content: &[u8] = b"all:\n\ttrue\nendif\n";
+        let e = Makefile::from_reader(
+            content,
+            Opts::default(),
+            Box::new(BufferLogger::default()),
+            Vars::new([]),
+        )
+        .unwrap_err();
+        assert_eq!(e.msg, "Unmatched 'endif'.");
+        assert_eq!(e.context.path, None);
+        assert_eq!(e.context.line_index, Some(2));
+    }
+
+    #[test]
+    fn test_unclosed_column() {
+        let column = |content: &str| {
+            let e = Makefile::from_reader(
+                content.as_bytes(),
+                Opts::default(),
+                Box::new(BufferLogger::default()),
+                Vars::new([]),
+            )
+            .unwrap_err();
+            (e.context.content.unwrap(), e.context.column_index)
+        };
+
+        // The prerequisites are expanded first, and also appear earlier in the line.
+        assert_eq!(column("t $(B: $(B\n"), ("t $(B: $(B".to_string(), Some(7)));
+
+        // Columns are within the logical line, after escapes and continuations.
+        assert_eq!(
+            column("A := x\\# $(B\n"),
+            ("A := x# $(B".to_string(), Some(8))
+        );
+        assert_eq!(
+            column("A := a \\\n  $(B\n"),
+            ("A := a $(B".to_string(), Some(7))
+        );
+        assert_eq!(
+            column("ifeq \"\" \"$(B\"\nendif\n"),
+            ("ifeq \"\" \"$(B\"".to_string(), Some(9))
+        );
+        assert_eq!(
+            column("X != $(shell echo\n"),
+            ("X != $(shell echo".to_string(), Some(5))
+        );
+    }
+
+    #[test]
+    fn test_targets() {
+        let content: &[u8] = b"%.o: %.c\nall:: b\nb a: c\n.SILENT:\nall:: a\n";
+        let makefile = Makefile::from_reader(
+            content,
+            Opts::default(),
+            Box::new(BufferLogger::default()),
+            Vars::new([]),
+        )
+        .unwrap();
+        assert_eq!(makefile.targets(), vec!["all", "b", "a", ".SILENT"]);
+        assert_eq!(makefile.rules_for("all").len(), 2);
+    }
+
+    #[test]
+    fn test_wildcard_unsorted() {
+        let scratch = ScratchDir::new().unwrap();
+        let dir = scratch.path();
+        for f in ["c.c", "a.c", "b.c"] {
+            fs::write(dir.join(f), "").unwrap();
+        }
+
+        let content = format!("FILES := $(wildcard {}/*.c)\n", dir.display());
+        let opts = Opts {
+            wildcard_unsorted: true,
+            ..Default::default()
+        };
+        let makefile = Makefile::from_reader(
+            content.as_bytes(),
+            opts,
+            Box::new(BufferLogger::default()),
+            Vars::new([]),
+        )
+        .unwrap();
+
+        // The matches are in whatever order the filesystem lists them.
+        let mut files = makefile
+            .vars
+            .get("FILES")
+            .value
+            .split(' ')
+            .collect::<Vec<_>>();
+        files.sort_unstable();
+        let d = dir.display();
+        assert_eq!(
+            files,
+            [format!("{d}/a.c"), format!("{d}/b.c"), format!("{d}/c.c")]
+        );
+    }
+
+    #[test]
+    fn test_capture_output() {
+        /// Collects recipe output, tagged with the stream it was written to.
+        #[derive(Default)]
+        struct RecipeLogger(std::sync::Mutex<Vec<String>>);
+
+        impl Logger for RecipeLogger {
+            fn write(&self, _msg: String) {}
+
+            fn recipe_stdout(&self, line: &str) {
+                self.0.lock().unwrap().push(format!("out: {line}"));
+            }
+
+            fn recipe_stderr(&self, line: &str) {
+                self.0.lock().unwrap().push(format!("err: {line}"));
+            }
+        }
+
+        let content: &[u8] = b"all:\n\techo a\n\t@echo b >&2\n";
+        let opts = Opts {
+            capture_output: true,
+            ..Default::default()
+        };
+        let makefile = Makefile::from_reader(
+            content,
+            opts,
+            Box::new(RecipeLogger::default()),
+            Vars::new([]),
+        )
+        .unwrap();
+        makefile.execute(vec![]).unwrap();
+        assert_eq!(
+            *makefile.logger.0.lock().unwrap(),
+            vec!["out: echo a", "out: a", "err: b"]
+        );
+    }
+
+    #[test]
+    fn test_write_database() {
+        let content: &[u8] = b"A = $(B)\nB := b\nall:: a | c\n\techo $(A)\n%.o: %.c\n\tcc\n";
+        let mut vars = Vars::new([]);
+        vars.clear_builtins();
+        let makefile = Makefile::from_reader(
+            content,
+            Opts::default(),
+            Box::new(BufferLogger::default()),
+            vars,
+        )
+        .unwrap();
+        let mut out = vec![];
+        makefile.write_database(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.starts_with("# Variables\n"));
+        assert!(out.contains("\n# file\nA = $(B)\n\n# file\nB := b\n"));
+        assert!(out.contains("\n# default\nSHELL := /bin/sh\n"));
+        assert!(out.ends_with("\n# Rules\n\nall:: a | c\n\techo $(A)\n\n%.o: %.c\n\tcc\n"));
+    }
+
+    #[test]
+    fn test_definedness_consistency() {
+        let content: &[u8] = b"EMPTY =\nSET = x\n\
+            EMPTY ?= changed\nNEW ?= new\n\
+            ifdef EMPTY\nIFDEF_EMPTY = yes\nendif\n\
+            undefine SET\nSET ?= again\n\
+            ORIGINS := $(origin EMPTY) $(origin NEW) $(origin UNSET)\n\
+            FLAVORS := $(flavor EMPTY) $(flavor ORIGINS) $(flavor UNSET)\n\
+            all:\n";
+        let makefile = Makefile::from_reader(
+            content,
+            Opts::default(),
+            Box::new(BufferLogger::default()),
+            Vars::new([]),
+        )
+        .unwrap();
+        let value = |k| makefile.vars.lookup(k).map(|v| v.value.as_str());
+
+        // A variable defined as empty is defined (for `?=` and `origin`), but not for `ifdef`.
+        assert_eq!(value("EMPTY"), Some(""));
+        assert_eq!(value("NEW"), Some("new"));
+        assert_eq!(value("IFDEF_EMPTY"), None);
+        assert_eq!(value("SET"), Some("again"));
+        assert_eq!(value("ORIGINS"), Some("file file undefined"));
+        assert_eq!(value("FLAVORS"), Some("recursive simple undefined"));
+    }
+
+    #[test]
+    fn test_parse_only() {
+        let scratch = ScratchDir::new().unwrap();
+        let dir = scratch.path();
+        let path = dir.join("Makefile");
+        fs::write(&path, "X = 1\nall: a b\n\techo $(X)\nall:\n\ttrue\n").unwrap();
+
+        let result = parse_only(path.clone(), Opts::default(), Vars::new([])).unwrap();
+        let rules = result.rule_map.get("all");
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].prerequisites, vec!["a", "b"]);
+        assert_eq!(rules[0].recipe, vec!["echo $(X)"]);
+        assert_eq!(result.vars.get("X").value, "1");
+        assert_eq!(result.makefile_list, vec![path.display().to_string()]);
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].contains("Ignoring duplicate definition."));
+        assert!(result.errors.is_empty());
+    }
+
+    #[test]
+    fn test_parse_only_tolerant() {
+        let scratch = ScratchDir::new().unwrap();
+        let dir = scratch.path();
+        let path = dir.join("Makefile");
+        fs::write(&path, "X = 1\n\techo orphan\nendif\nall:\n\ttrue\n").unwrap();
+
+        let opts = Opts {
+            tolerant: true,
+            ..Default::default()
+        };
+        let result = parse_only(path.clone(), opts, Vars::new([])).unwrap();
+        let errors = result
+            .errors
+            .iter()
+            .map(|e| (e.msg.as_str(), e.context.line_index))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    "Recipe without rule (the preceding line is a variable assignment).",
+                    Some(1)
+                ),
+                ("Unmatched 'endif'.", Some(2)),
+            ]
+        );
+        assert_eq!(result.rule_map.get("all").len(), 1);
+
+        // Without `tolerant`, parsing stops at the first error.
+        let e = parse_only(path, Opts::default(), Vars::new([])).unwrap_err();
+        assert_eq!(e.context.line_index, Some(1));
+    }
+}
@@ -0,0 +1,196 @@
+//! Makefile dialects (GNU and BSD), which differ mainly in the syntax of directives.
+
+use std::fs;
+use std::path::Path;
+
+/// BSD directives, which start with a `.` (possibly followed by whitespace, for indenting nested
+/// directives, e.g., `.  if`).
+const BSD_DIRECTIVES: [&str; 10] = [
+    "if", "ifdef", "ifndef", "elif", "else", "endif", "include", "-include", "sinclude", "dinclude",
+];
+
+/// GNU directives which can identify the dialect of a makefile.
+const GNU_DIRECTIVES: [&str; 7] = [
+    "ifeq", "ifneq", "ifdef", "ifndef", "include", "-include", "define",
+];
+
+/// The dialect used to parse a makefile.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dialect {
+    /// Detect the dialect from the name of the makefile (e.g., `BSDmakefile`), or else from the
+    /// first directive in it, defaulting to GNU.
+    #[default]
+    Auto,
+
+    /// GNU make syntax (e.g., `ifeq (a,b)` and `include file`).
+    Gnu,
+
+    /// BSD make syntax (e.g., `.if ${A} == b` and `.include "file"`).
+    Bsd,
+}
+
+impl Dialect {
+    /// Parse the value of `--dialect`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Self::Auto),
+            "gnu" => Some(Self::Gnu),
+            "bsd" => Some(Self::Bsd),
+            _ => None,
+        }
+    }
+
+    /// Resolve `Auto` to a concrete dialect for the makefile at `path`. Other dialects are
+    /// returned as they are.
+    pub fn resolve(self, path: &Path) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+
+        let name = path.file_name().map(|n| n.to_string_lossy());
+        match name.as_deref() {
+            Some(n) if n.starts_with("BSD") => return Self::Bsd,
+            Some(n) if n.starts_with("GNU") => return Self::Gnu,
+            _ => {}
+        }
+
+        // Otherwise, the first line which is a directive of either dialect decides. Recipe lines
+        // are skipped, since they are passed to the shell.
+        let content = fs::read_to_string(path).unwrap_or_default();
+        for line in content.lines().filter(|l| !l.starts_with('\t')) {
+            if bsd_directive(line).is_some() {
+                return Self::Bsd;
+            }
+            let word = line.split_whitespace().next().unwrap_or_default();
+            if GNU_DIRECTIVES.contains(&word) {
+                return Self::Gnu;
+            }
+        }
+
+        Self::Gnu
+    }
+}
+
+/// If `line` is a BSD directive (e.g., `.if ${A} == b`), then return its name (without the `.`)
+/// and the rest of the line.
+pub fn bsd_directive(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_start().strip_prefix('.')?.trim_start();
+    let end = line.find(char::is_whitespace).unwrap_or(line.len());
+    let name = BSD_DIRECTIVES.iter().find(|&&d| d == &line[..end])?;
+    Some((name, line[end..].trim()))
+}
+
+/// Split a BSD conditional expression on the logical operator `op` (`||` or `&&`), ignoring
+/// operators within quotes or nested expressions.
+pub fn split_logical<'a>(expr: &'a str, op: &str) -> Vec<&'a str> {
+    let mut parts = vec![];
+    let mut depth: usize = 0;
+    let mut quote = None;
+    let mut start = 0;
+
+    for (i, c) in expr.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') => quote = Some('"'),
+            (None, '(' | '{') => depth += 1,
+            (None, ')' | '}') => depth = depth.saturating_sub(1),
+            (None, _) if depth == 0 && i >= start && expr[i..].starts_with(op) => {
+                parts.push(expr[start..i].trim());
+                start = i + op.len();
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(expr[start..].trim());
+    parts
+}
+
+/// Split a BSD comparison (e.g., `${A} == b`) into its operands and operator, ignoring operators
+/// within quotes or nested expressions. Returns `None` if there is no comparison operator.
+pub fn split_comparison(expr: &str) -> Option<(&str, &str, &str)> {
+    let mut depth: usize = 0;
+    let mut quoted = false;
+
+    for (i, c) in expr.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' | '{' if !quoted => depth += 1,
+            ')' | '}' if !quoted => depth = depth.saturating_sub(1),
+            '=' | '!' | '<' | '>' if !quoted && depth == 0 => {
+                let op = ["==", "!=", "<=", ">=", "<", ">"]
+                    .into_iter()
+                    .find(|op| expr[i..].starts_with(op))?;
+                return Some((expr[..i].trim(), op, expr[i + op.len()..].trim()));
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// If `expr` is a call of the BSD conditional function `name` (e.g., `defined(VAR)`), then return
+/// its argument.
+pub fn bsd_function<'a>(expr: &'a str, name: &str) -> Option<&'a str> {
+    expr.strip_prefix(name)?
+        .trim_start()
+        .strip_prefix('(')?
+        .strip_suffix(')')
+        .map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::makefile::ScratchDir;
+
+    #[test]
+    fn test_bsd_directive() {
+        assert_eq!(bsd_directive(".if ${A} == b"), Some(("if", "${A} == b")));
+        assert_eq!(bsd_directive(".  endif"), Some(("endif", "")));
+        assert_eq!(
+            bsd_directive(".include \"a.mk\""),
+            Some(("include", "\"a.mk\""))
+        );
+        assert_eq!(bsd_directive(".PHONY: all"), None);
+        assert_eq!(bsd_directive(".iffy"), None);
+        assert_eq!(bsd_directive("if"), None);
+    }
+
+    #[test]
+    fn test_split_logical() {
+        assert_eq!(split_logical("a || b||c", "||"), vec!["a", "b", "c"]);
+        assert_eq!(split_logical("\"a||b\" || c", "||"), vec!["\"a||b\"", "c"]);
+        assert_eq!(split_logical("${A:||} && b", "&&"), vec!["${A:||}", "b"]);
+    }
+
+    #[test]
+    fn test_split_comparison() {
+        assert_eq!(split_comparison("${A} == b"), Some(("${A}", "==", "b")));
+        assert_eq!(split_comparison("1>=2"), Some(("1", ">=", "2")));
+        assert_eq!(
+            split_comparison("\"a==b\" != c"),
+            Some(("\"a==b\"", "!=", "c"))
+        );
+        assert_eq!(split_comparison("defined(A)"), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        for name in ["BSDmakefile", "GNUmakefile"] {
+            let dialect = Dialect::Auto.resolve(Path::new(name));
+            assert_eq!(dialect == Dialect::Bsd, name.starts_with("BSD"));
+        }
+        assert_eq!(Dialect::Bsd.resolve(Path::new("GNUmakefile")), Dialect::Bsd);
+
+        let scratch = ScratchDir::new().unwrap();
+        let dir = scratch.path();
+        let path = dir.join("Makefile");
+        fs::write(&path, "A = 1\nall:\n\t.if\n.if ${A}\n.endif\n").unwrap();
+        assert_eq!(Dialect::Auto.resolve(&path), Dialect::Bsd);
+        fs::write(&path, "A = 1\nifdef A\nendif\n.if ${A}\n.endif\n").unwrap();
+        assert_eq!(Dialect::Auto.resolve(&path), Dialect::Gnu);
+    }
+}
@@ -0,0 +1,113 @@
+//! State which is tracked while executing a makefile.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::time::SystemTime;
+
+use super::parallel::Plan;
+use super::{Logger, Makefile};
+
+/// A target file which was created or modified while executing a makefile.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileChange {
+    Created(String),
+    Modified(String),
+}
+
+impl FileChange {
+    pub fn file(&self) -> &str {
+        match self {
+            Self::Created(file) | Self::Modified(file) => file,
+        }
+    }
+}
+
+/// Get the actual `mtime` of a file, ignoring `--old-file` and `--new-file`.
+pub(crate) fn file_mtime(file: &str) -> Option<SystemTime> {
+    fs::metadata(file).and_then(|m| m.modified()).ok()
+}
+
+/// Per-run state which is threaded through the recursive execution of targets, so that state can
+/// be shared across the dependency graph without adding parameters for each feature.
+#[derive(Debug, Default)]
+pub struct ExecContext {
+    /// Cache of file `mtime`s, since the same files are often checked many times during a run.
+    mtimes: HashMap<String, Option<SystemTime>>,
+
+    /// Targets which are currently being made, used to detect circular dependencies.
+    pub in_progress: HashSet<String>,
+
+    /// Targets which have been made (or found to be up to date) this run, so each is only checked
+    /// once, even when it is a prerequisite of several targets.
+    pub made: HashSet<String>,
+
+    /// Targets which failed to be made (only more than one when keeping going after errors).
+    pub failed: Vec<String>,
+
+    /// Targets which were not attempted because a prerequisite failed (when keeping going).
+    pub skipped: Vec<String>,
+
+    /// Target files which were created or modified by their recipes, in the order they were made.
+    pub changes: Vec<FileChange>,
+
+    /// Whether any target was out of date, so its recipe was run (or would have been, under `-q`).
+    pub out_of_date: bool,
+
+    /// Under `-j`, rules which need to run are added to this plan rather than being run directly.
+    pub plan: Option<Plan>,
+}
+
+impl ExecContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the (cached) `mtime` of a file. Phony targets are treated as nonexistent, so they are
+    /// always out of date, even if a file of the same name exists.
+    pub fn mtime<L: Logger>(
+        &mut self,
+        makefile: &Makefile<L>,
+        file: &String,
+    ) -> Option<SystemTime> {
+        if makefile.rule_map.is_phony(file) {
+            return None;
+        }
+
+        if let Some(mtime) = self.mtimes.get(file) {
+            return *mtime;
+        }
+
+        // Files which don't exist as named may be found in a `VPATH` directory.
+        let mtime = makefile.get_mtime(file).or_else(|| {
+            makefile
+                .vpath_search(file)
+                .and_then(|path| makefile.get_mtime(&path))
+        });
+        self.mtimes.insert(file.clone(), mtime);
+        mtime
+    }
+
+    /// Whether a job to make `target` has been planned (under `-j`).
+    pub fn is_planned(&self, target: &str) -> bool {
+        self.plan.as_ref().is_some_and(|plan| plan.contains(target))
+    }
+
+    /// Record whether the recipe for `target` created or modified its file, given its `mtime` from
+    /// before the recipe ran.
+    pub fn record_change(&mut self, target: &str, before: Option<SystemTime>) {
+        let after = file_mtime(target);
+        if after.is_none() || after == before || self.changes.iter().any(|c| c.file() == target) {
+            return;
+        }
+
+        self.changes.push(match before {
+            None => FileChange::Created(target.to_string()),
+            Some(_) => FileChange::Modified(target.to_string()),
+        });
+    }
+
+    /// Forget the cached `mtime` of a file, e.g., because its recipe was just executed.
+    pub fn invalidate(&mut self, file: &str) {
+        self.mtimes.remove(file);
+    }
+}
@@ -0,0 +1,285 @@
+//! A jobserver compatible with GNU make, which limits the number of jobs run at once across a
+//! `make` and its sub-makes by sharing a pool of tokens.
+//!
+//! Each `make` has one implicit token, so a jobserver for `N` jobs starts with `N - 1` tokens in the
+//! pool. Sub-makes find the pool via `--jobserver-auth` in `MAKEFLAGS`, which is either
+//! `fifo:PATH` for a named pipe (the default since GNU make 4.4), or `R,W` for the file descriptors
+//! of an anonymous pipe inherited from the parent (the legacy style).
+
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::Path;
+
+/// A handle on a pool of job tokens, which may have been created by this process or inherited.
+#[derive(Debug)]
+pub struct Jobserver {
+    read: File,
+    write: File,
+
+    /// Another handle on the read end in non-blocking mode, for `try_acquire`, if it could be
+    /// opened. It is a separate open file description, so the read end shared with other processes
+    /// stays blocking.
+    try_read: Option<File>,
+
+    /// The `--jobserver-auth` value which lets sub-makes use this pool.
+    auth: String,
+}
+
+/// A token taken from the pool, which is returned when dropped.
+#[derive(Debug)]
+pub struct Token<'a> {
+    jobserver: &'a Jobserver,
+    byte: u8,
+}
+
+impl Drop for Token<'_> {
+    fn drop(&mut self) {
+        let _ = (&self.jobserver.write).write_all(&[self.byte]);
+    }
+}
+
+impl Jobserver {
+    /// Create a jobserver allowing `jobs` jobs at once (including the implicit token), shared
+    /// through a named pipe created in `dir` (e.g., the scratch directory of the `Makefile`, which
+    /// removes it on drop).
+    pub fn fifo(jobs: usize, dir: &Path) -> io::Result<Self> {
+        let path = dir.join("jobserver");
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+
+        // SAFETY: `c_path` is a valid NUL-terminated string.
+        if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Self::open_fifo(&path.to_string_lossy())?.fill(jobs)
+    }
+
+    /// Create a jobserver allowing `jobs` jobs at once (including the implicit token), shared
+    /// through an anonymous pipe which is inherited by sub-makes.
+    pub fn pipe(jobs: usize) -> io::Result<Self> {
+        let mut fds = [0; 2];
+
+        // SAFETY: `fds` has room for the two file descriptors. They are created without
+        // `O_CLOEXEC`, so they are inherited by sub-makes.
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: The file descriptors were just created, so nothing else owns them.
+        Self {
+            read: unsafe { File::from_raw_fd(fds[0]) },
+            write: unsafe { File::from_raw_fd(fds[1]) },
+            try_read: open_nonblocking(&format!("/proc/self/fd/{}", fds[0])),
+            auth: format!("{},{}", fds[0], fds[1]),
+        }
+        .fill(jobs)
+    }
+
+    /// Put the tokens for `jobs` jobs into a new pool, leaving out the implicit token.
+    fn fill(self, jobs: usize) -> io::Result<Self> {
+        (&self.write).write_all(&vec![b'+'; jobs.saturating_sub(1)])?;
+        Ok(self)
+    }
+
+    /// Connect to the pool described by a `--jobserver-auth` value from a parent `make`.
+    pub fn from_auth(auth: &str) -> io::Result<Self> {
+        if let Some(path) = auth.strip_prefix("fifo:") {
+            return Self::open_fifo(path);
+        }
+
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "invalid jobserver auth");
+        let (read, write) = auth.split_once(',').ok_or_else(invalid)?;
+        let read = read.parse::<i32>().map_err(|_| invalid())?;
+        let write = write.parse::<i32>().map_err(|_| invalid())?;
+
+        // Make sure the inherited file descriptors are actually open, since a parent which is not
+        // a jobserver-aware `make` may not have passed them on.
+        for fd in [read, write] {
+            // SAFETY: `fcntl` with `F_GETFD` only queries the file descriptor.
+            if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        // SAFETY: The file descriptors were checked above and were passed to us to use.
+        Ok(Self {
+            read: unsafe { File::from_raw_fd(read) },
+            write: unsafe { File::from_raw_fd(write) },
+            try_read: open_nonblocking(&format!("/proc/self/fd/{}", read)),
+            auth: auth.to_string(),
+        })
+    }
+
+    /// Open both ends of a named pipe. Opening for both reading and writing means neither open
+    /// blocks waiting for the other end.
+    fn open_fifo(path: &str) -> io::Result<Self> {
+        let read = OpenOptions::new().read(true).write(true).open(path)?;
+        let write = OpenOptions::new().write(true).open(path)?;
+
+        Ok(Self {
+            read,
+            write,
+            try_read: open_nonblocking(path),
+            auth: format!("fifo:{}", path),
+        })
+    }
+
+    /// The value for `--jobserver-auth` in `MAKEFLAGS` so sub-makes share this pool.
+    pub fn auth(&self) -> &str {
+        &self.auth
+    }
+
+    /// Take a token from the pool if one is available right now, without blocking on an empty pool.
+    /// The read itself is non-blocking, so a sub-make taking the last token first is not a problem.
+    pub fn try_acquire(&self) -> io::Result<Option<Token<'_>>> {
+        if let Some(read) = &self.try_read {
+            return self.read_token(read);
+        }
+
+        // Without a separate non-blocking handle (e.g., an anonymous pipe outside of Linux, where
+        // there is no `/proc/self/fd`), the shared read end is made non-blocking just for this read.
+        let fd = self.read.as_raw_fd();
+
+        // SAFETY: `fcntl` with `F_GETFL` only queries the file descriptor.
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        // SAFETY: `F_SETFL` only changes the file status flags of a file descriptor we own.
+        if flags == -1 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } == -1
+        {
+            return Err(io::Error::last_os_error());
+        }
+        let token = self.read_token(&self.read);
+        // SAFETY: As above, this restores the flags read before.
+        unsafe { libc::fcntl(fd, libc::F_SETFL, flags) };
+        token
+    }
+
+    /// Take a token from the pool, blocking until one is available.
+    #[cfg(test)]
+    fn acquire(&self) -> io::Result<Token<'_>> {
+        // A blocking read never returns `WouldBlock`, so this `unwrap()` is safe.
+        Ok(self.read_token(&self.read)?.unwrap())
+    }
+
+    /// Read a token from `read`, returning `None` if it is non-blocking and the pool is empty.
+    fn read_token(&self, mut read: &File) -> io::Result<Option<Token<'_>>> {
+        let mut byte = [0];
+        loop {
+            match read.read(&mut byte) {
+                Ok(1) => {
+                    return Ok(Some(Token {
+                        jobserver: self,
+                        byte: byte[0],
+                    }))
+                }
+                Ok(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "jobserver closed",
+                    ))
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Open the read end of a pipe (given by path) as a new, non-blocking open file description. For
+/// an anonymous pipe, this is done through `/proc/self/fd`, which only exists on some platforms
+/// (e.g., Linux).
+fn open_nonblocking(path: &str) -> Option<File> {
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::makefile::ScratchDir;
+
+    #[test]
+    fn test_fifo_tokens_are_shared() {
+        let dir = ScratchDir::new().unwrap();
+        let jobserver = Jobserver::fifo(3, dir.path()).unwrap();
+        let path = jobserver.auth().strip_prefix("fifo:").unwrap().to_string();
+
+        // A sub-make would connect to the same pool through the auth string.
+        let other = Jobserver::from_auth(jobserver.auth()).unwrap();
+        let a = jobserver.acquire().unwrap();
+        let b = other.acquire().unwrap();
+        drop(a);
+        let c = other.acquire().unwrap();
+        drop((b, c));
+
+        drop(other);
+        drop(jobserver);
+        drop(dir);
+        assert!(!Path::new(&path).exists());
+    }
+
+    #[test]
+    fn test_pipe_auth() {
+        let jobserver = Jobserver::pipe(2).unwrap();
+        let (read, write) = jobserver.auth().split_once(',').unwrap();
+        assert_eq!(read.parse::<i32>().unwrap(), jobserver.read.as_raw_fd());
+        assert!(write.parse::<i32>().is_ok());
+
+        let token = jobserver.acquire().unwrap();
+        drop(token);
+        jobserver.acquire().unwrap();
+    }
+
+    #[test]
+    fn test_try_acquire() {
+        let jobserver = Jobserver::pipe(2).unwrap();
+        let token = jobserver.try_acquire().unwrap().unwrap();
+        assert!(jobserver.try_acquire().unwrap().is_none());
+        drop(token);
+        assert!(jobserver.try_acquire().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_try_acquire_leaves_shared_read_end_blocking() {
+        let dir = ScratchDir::new().unwrap();
+        for jobserver in [Jobserver::fifo(2, dir.path()), Jobserver::pipe(2)] {
+            let jobserver = jobserver.unwrap();
+            let token = jobserver.try_acquire().unwrap().unwrap();
+            assert!(jobserver.try_acquire().unwrap().is_none());
+            drop(token);
+
+            // SAFETY: `fcntl` with `F_GETFL` only queries the file descriptor.
+            let flags = unsafe { libc::fcntl(jobserver.read.as_raw_fd(), libc::F_GETFL) };
+            assert_eq!(flags & libc::O_NONBLOCK, 0);
+        }
+    }
+
+    #[test]
+    fn test_try_acquire_without_nonblocking_handle() {
+        let jobserver = Jobserver {
+            try_read: None,
+            ..Jobserver::pipe(2).unwrap()
+        };
+        let token = jobserver.try_acquire().unwrap().unwrap();
+        assert!(jobserver.try_acquire().unwrap().is_none());
+        drop(token);
+        assert!(jobserver.try_acquire().unwrap().is_some());
+
+        // SAFETY: `fcntl` with `F_GETFL` only queries the file descriptor.
+        let flags = unsafe { libc::fcntl(jobserver.read.as_raw_fd(), libc::F_GETFL) };
+        assert_eq!(flags & libc::O_NONBLOCK, 0);
+    }
+
+    #[test]
+    fn test_invalid_auth() {
+        assert!(Jobserver::from_auth("nonsense").is_err());
+        assert!(Jobserver::from_auth("fifo:/omake/does/not/exist").is_err());
+    }
+}
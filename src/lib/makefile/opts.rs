@@ -1,19 +1,192 @@
 //! Options available for makefiles.
 
+use super::Dialect;
+
+/// How the jobserver's token pool is shared with sub-makes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JobserverStyle {
+    /// A named pipe, which sub-makes open by path.
+    #[default]
+    Fifo,
+
+    /// An anonymous pipe, whose file descriptors are inherited by sub-makes.
+    Pipe,
+}
+
+impl JobserverStyle {
+    /// Parse the value of `--jobserver-style`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fifo" => Some(Self::Fifo),
+            "pipe" => Some(Self::Pipe),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Opts {
     /// Unconditionally make all targets.
     pub always_make: bool,
 
+    /// Print debugging information.
+    pub debug: bool,
+
+    /// Also print debugging information about searching for rules and files.
+    pub debug_verbose: bool,
+
+    /// Log why each target's recipe runs (e.g., which prerequisite is newer).
+    pub trace: bool,
+
+    /// Print the variables and rules after parsing, rather than making any targets.
+    pub print_database: bool,
+
     /// Ignore errors from recipes.
     pub ignore_errors: bool,
 
+    /// Maximum number of jobs to run at once (jobs are run one at a time unless this is above 1).
+    pub jobs: usize,
+
+    /// Keep going when some targets can't be made.
+    pub keep_going: bool,
+
     /// Don't execute recipes; just print them.
     pub just_print: bool,
 
+    /// Don't execute recipes or print anything; just check whether the targets are up to date.
+    pub question: bool,
+
+    /// Disable the builtin variables (other than those needed to execute recipes).
+    pub no_builtin_variables: bool,
+
+    /// Don't echo recipe commands.
+    pub silent: bool,
+
+    /// Capture the output of recipes and pass it to the logger line by line (see
+    /// `Logger::recipe_stdout`), rather than letting recipes write to the terminal directly.
+    pub capture_output: bool,
+
+    /// List `$(wildcard)` matches in the order the filesystem lists them (as with some versions of
+    /// GNU make), rather than sorted.
+    pub wildcard_unsorted: bool,
+
     /// Consider FILE to be very old and do not remake it.
     pub old_file: Vec<String>,
 
     /// Consider FILE to be very new to simulate "what if" it changed.
     pub new_file: Vec<String>,
+
+    /// Keep parsing after an error, collecting every error rather than stopping at the first (e.g.,
+    /// for linters). The makefile still can't be executed unless it parses cleanly.
+    pub tolerant: bool,
+
+    /// Fail right after parsing if the makefile defines no rules at all.
+    pub require_rules: bool,
+
+    /// How the jobserver shares job tokens with sub-makes.
+    pub jobserver_style: JobserverStyle,
+
+    /// The jobserver inherited from a parent `make` (the `--jobserver-auth` value).
+    pub jobserver_auth: Option<String>,
+
+    /// The dialect used to parse makefiles (GNU or BSD), which is detected by default.
+    pub dialect: Dialect,
+}
+
+impl Opts {
+    /// Compute the `MAKEFLAGS` representation of these options, which is a cluster of the
+    /// single-letter flags (without a leading dash), followed by any flags which take values.
+    pub fn makeflags(&self) -> String {
+        let mut flags = [
+            ('B', self.always_make),
+            ('d', self.debug && !self.debug_verbose),
+            ('i', self.ignore_errors),
+            ('k', self.keep_going),
+            ('n', self.just_print),
+            ('p', self.print_database),
+            ('q', self.question),
+            ('R', self.no_builtin_variables),
+            ('s', self.silent),
+        ]
+        .iter()
+        .filter(|(_, set)| *set)
+        .map(|(flag, _)| flag.to_string())
+        .collect::<Vec<_>>()
+        .concat();
+
+        if self.debug_verbose {
+            flags.push_str(" --debug=verbose");
+        }
+        if self.trace {
+            flags.push_str(" --trace");
+        }
+
+        for (flag, files) in [('o', &self.old_file), ('W', &self.new_file)] {
+            for file in files {
+                flags.push_str(&format!(" -{} {}", flag, file));
+            }
+        }
+
+        flags.trim_start().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_makeflags() {
+        assert_eq!(Opts::default().makeflags(), "");
+
+        let opts = Opts {
+            keep_going: true,
+            just_print: true,
+            silent: true,
+            old_file: vec!["a".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(opts.makeflags(), "kns -o a");
+
+        let opts = Opts {
+            new_file: vec!["b".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(opts.makeflags(), "-W b");
+
+        let opts = Opts {
+            debug: true,
+            debug_verbose: true,
+            keep_going: true,
+            ..Default::default()
+        };
+        assert_eq!(opts.makeflags(), "k --debug=verbose");
+
+        let opts = Opts {
+            trace: true,
+            ..Default::default()
+        };
+        assert_eq!(opts.makeflags(), "--trace");
+
+        let opts = Opts {
+            print_database: true,
+            question: true,
+            silent: true,
+            ..Default::default()
+        };
+        assert_eq!(opts.makeflags(), "pqs");
+    }
+
+    #[test]
+    fn test_jobserver_style_from_name() {
+        assert_eq!(
+            JobserverStyle::from_name("fifo"),
+            Some(JobserverStyle::Fifo)
+        );
+        assert_eq!(
+            JobserverStyle::from_name("pipe"),
+            Some(JobserverStyle::Pipe)
+        );
+        assert_eq!(JobserverStyle::from_name("sem"), None);
+    }
 }
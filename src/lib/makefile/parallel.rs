@@ -0,0 +1,265 @@
+//! Parallel execution of recipes (`-j N`).
+//!
+//! Parallel execution happens in two phases. First, the targets are checked as usual, but rather
+//! than running recipes, each rule which needs to run is added to a [`Plan`] as a [`Job`], along
+//! with the jobs it depends on. Then the plan is run, starting each job once its dependencies are
+//! done and a job slot is free. Each `make` has one implicit slot, and the others are tokens taken
+//! from the [`Jobserver`], which is shared with any sub-makes.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use super::exec_context::file_mtime;
+use super::rule_map::{log_recipe_output, Rule};
+use super::{ExecContext, Jobserver, Logger, MakeError, Makefile};
+
+/// How long to wait for a job to finish before checking for a free token again.
+const TOKEN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A rule which needs to run to make a target.
+#[derive(Debug)]
+pub struct Job {
+    pub target: String,
+    pub rule: Rule,
+
+    /// Jobs which must finish before this one starts, by index into the plan.
+    pub deps: Vec<usize>,
+}
+
+/// The jobs needed to make the goals, in an order where each job comes after its dependencies.
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub jobs: Vec<Job>,
+
+    /// Map targets to the jobs which make them (more than one only for double-colon rules).
+    provides: HashMap<String, Vec<usize>>,
+}
+
+impl Plan {
+    /// Add a job to run `rule` for `target`, after the jobs for its prerequisites and any earlier
+    /// jobs for the same target.
+    pub fn add(&mut self, target: &str, rule: Rule) {
+        let deps = rule
+            .prerequisites
+            .iter()
+            .chain(&rule.order_only)
+            .map(String::as_str)
+            .chain([target])
+            .filter_map(|t| self.provides.get(t))
+            .flatten()
+            .copied()
+            .collect();
+
+        self.provides
+            .entry(target.to_string())
+            .or_default()
+            .push(self.jobs.len());
+        self.jobs.push(Job {
+            target: target.to_string(),
+            rule,
+            deps,
+        });
+    }
+
+    /// Whether a job has been added for `target`.
+    pub fn contains(&self, target: &str) -> bool {
+        self.provides.contains_key(target)
+    }
+}
+
+/// Output from the commands of a job, which is held until the job is done so that the output of
+/// concurrent jobs is not interleaved.
+#[derive(Debug, Default)]
+pub struct JobOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum JobState {
+    Pending,
+    Running,
+    Done,
+    Failed,
+
+    /// Not run because a job it depends on failed (or was itself skipped).
+    Skipped,
+}
+
+/// Run the jobs in `plan`, using up to `-j` job slots. Failed targets are recorded in `ctx.failed`
+/// and targets which were not attempted because of them are recorded in `ctx.skipped`.
+///
+/// Under `keep_going`, errors are logged as jobs fail, and the other jobs carry on. Otherwise, no
+/// new jobs are started after the first failure, and once the running jobs are done, the errors
+/// are reported in the order of the plan (rather than the order they happened), the last being
+/// returned and any others logged.
+pub fn run<L: Logger + Sync>(
+    makefile: &Makefile<L>,
+    plan: Plan,
+    jobserver: &Jobserver,
+    ctx: &mut ExecContext,
+) -> Result<(), MakeError> {
+    let mut states = vec![JobState::Pending; plan.jobs.len()];
+
+    // The `mtime` of each target from just before its job started, to detect changes.
+    let mut before = vec![None; plan.jobs.len()];
+    let mut errors = vec![];
+
+    thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+
+        // Tokens held by the running jobs, which are returned to the pool when dropped. The job
+        // using the implicit slot has no token, and the slot is free again once that job is done
+        // (even if jobs holding tokens are still running).
+        let mut tokens = HashMap::new();
+        let mut implicit_free = true;
+        let mut running = 0;
+
+        loop {
+            // Jobs come after their dependencies, so one pass propagates skips down the plan.
+            for (i, job) in plan.jobs.iter().enumerate() {
+                if states[i] == JobState::Pending
+                    && job
+                        .deps
+                        .iter()
+                        .any(|&d| matches!(states[d], JobState::Failed | JobState::Skipped))
+                {
+                    states[i] = JobState::Skipped;
+                    if !ctx.skipped.contains(&job.target) {
+                        ctx.skipped.push(job.target.clone());
+                    }
+                }
+            }
+
+            // Start as many ready jobs as there are free slots, unless stopping after an error.
+            let mut waiting = false;
+            if errors.is_empty() || makefile.opts.keep_going {
+                for (i, job) in plan.jobs.iter().enumerate() {
+                    let ready = states[i] == JobState::Pending
+                        && job.deps.iter().all(|&d| states[d] == JobState::Done);
+                    if !ready {
+                        continue;
+                    }
+
+                    if implicit_free {
+                        implicit_free = false;
+                    } else {
+                        match jobserver.try_acquire() {
+                            Ok(Some(token)) => {
+                                tokens.insert(i, token);
+                            }
+                            _ => {
+                                waiting = true;
+                                break;
+                            }
+                        }
+                    }
+
+                    before[i] = file_mtime(&job.target);
+                    states[i] = JobState::Running;
+                    running += 1;
+                    let tx = tx.clone();
+                    scope.spawn(move || {
+                        let mut output = JobOutput::default();
+                        let result = job.rule.execute(makefile, &job.target, Some(&mut output));
+                        let _ = tx.send((i, output, result));
+                    });
+                }
+            }
+
+            if running == 0 {
+                break;
+            }
+
+            // Wait for a job to finish, but if a job is ready and only waiting for a token, then
+            // check for a free token again shortly.
+            let (i, output, result) = if waiting {
+                match rx.recv_timeout(TOKEN_POLL_INTERVAL) {
+                    Ok(finished) => finished,
+                    Err(_) => continue,
+                }
+            } else {
+                // This `unwrap()` is safe because a running job always sends its result.
+                rx.recv().unwrap()
+            };
+            running -= 1;
+            if tokens.remove(&i).is_none() {
+                implicit_free = true;
+            }
+
+            // Write errors are ignored, as when echoing commands.
+            if makefile.opts.capture_output {
+                log_recipe_output(&*makefile.logger, &output.stdout, &output.stderr);
+            } else {
+                let _ = io::stdout().write_all(&output.stdout);
+                let _ = io::stderr().write_all(&output.stderr);
+            }
+
+            let target = &plan.jobs[i].target;
+            match result {
+                Ok(()) => {
+                    states[i] = JobState::Done;
+                    ctx.invalidate(target);
+                    ctx.record_change(target, before[i]);
+                }
+                Err(e) => {
+                    states[i] = JobState::Failed;
+                    if !ctx.failed.contains(target) {
+                        ctx.failed.push(target.clone());
+                    }
+                    if makefile.opts.keep_going {
+                        makefile.logger.error(e.msg, Some(&e.context));
+                    } else {
+                        errors.push((i, e));
+                    }
+                }
+            }
+        }
+    });
+
+    errors.sort_by_key(|(i, _)| *i);
+    match errors.pop() {
+        None => Ok(()),
+        Some((_, last)) => {
+            for (_, e) in errors {
+                makefile.logger.error(e.msg, Some(&e.context));
+            }
+            Err(last)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Context;
+
+    fn rule(prerequisites: &[&str]) -> Rule {
+        Rule {
+            targets: vec![],
+            prerequisites: prerequisites.iter().map(|p| p.to_string()).collect(),
+            order_only: vec![],
+            recipe: vec![],
+            context: Context::new(),
+            double_colon: false,
+        }
+    }
+
+    #[test]
+    fn test_plan_deps() {
+        let mut plan = Plan::default();
+        plan.add("a", rule(&[]));
+        plan.add("b", rule(&["a"]));
+        plan.add("c", rule(&["x"]));
+        plan.add("all", rule(&["b", "c", "a"]));
+        plan.add("all", rule(&[]));
+
+        let deps = plan.jobs.iter().map(|j| j.deps.clone()).collect::<Vec<_>>();
+        assert_eq!(deps, vec![vec![], vec![0], vec![], vec![1, 2, 0], vec![3]]);
+        assert!(plan.contains("all"));
+        assert!(!plan.contains("x"));
+    }
+}
@@ -1,66 +1,282 @@
 //! Data structures for makefile rules.
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
 use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use super::{Context, Logger, MakeError, Makefile};
+use super::exec_context::file_mtime;
+use super::parallel::JobOutput;
+use super::{Context, ExecContext, Logger, MakeError, Makefile, Vars};
+use crate::expand::{pattern_stem, substitute_stem};
+use crate::vars::Origin;
 
 /// A parsed rule from a makefile.
 #[derive(Debug, Clone)]
 pub struct Rule {
     pub targets: Vec<String>,
     pub prerequisites: Vec<String>,
+    /// Prerequisites which must be built before this rule, but which never cause it to run.
+    pub order_only: Vec<String>,
     pub recipe: Vec<String>,
     pub context: Context,
     pub double_colon: bool,
 }
 
 impl Rule {
-    pub fn execute<L: Logger>(&self, makefile: &Makefile<L>) -> Result<(), MakeError> {
-        let shell = &makefile.vars.get("SHELL").value;
-        let shell_flags = makefile
-            .vars
-            .get(".SHELLFLAGS")
-            .value
-            .split_whitespace()
-            .collect::<Vec<_>>();
-
-        for line in self.recipe.iter() {
-            // Determine if the first character is a command modifier.
-            let command_modifier = match line.chars().next().unwrap() {
-                ch @ ('@' | '-' | '+') => Some(ch),
-                _ => None,
-            };
+    /// Prerequisites with duplicates removed, preserving the order of their first occurrence. This
+    /// is the value of `$^`, whereas `$+` is just the `prerequisites` as they were written.
+    pub fn unique_prerequisites(&self) -> Vec<&str> {
+        let mut seen = HashSet::new();
+        self.prerequisites
+            .iter()
+            .map(|p| p.as_str())
+            .filter(|p| seen.insert(*p))
+            .collect()
+    }
+
+    /// Copy the makefile's variables and bind the target-specific and automatic variables for
+    /// building `target`.
+    fn automatic_vars<L: Logger>(&self, makefile: &Makefile<L>, target: &str) -> Vars {
+        let mut vars = makefile.vars.clone();
 
-            // Echo the line to stdout, unless suppressed.
-            if command_modifier != Some('@') || makefile.opts.just_print {
-                println!("{}", line);
+        // Prerequisites found through `VPATH` are referred to by the path where they were found.
+        let locate = |p: &str| makefile.vpath_search(p).unwrap_or_else(|| p.to_string());
+        let located = |prereqs: &[String]| -> String {
+            prereqs
+                .iter()
+                .map(|p| locate(p))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        // This `unwrap()` is safe because target-specific variable names are validated by the
+        // parser.
+        for (k, v, recursive) in makefile.target_vars.get(target).into_iter().flatten() {
+            vars.set(k.as_str(), v.as_str(), *recursive).unwrap();
+        }
+
+        for (k, v) in [
+            ("@", target.to_string()),
+            (
+                "<",
+                self.prerequisites
+                    .first()
+                    .map(|p| locate(p))
+                    .unwrap_or_default(),
+            ),
+            (
+                "^",
+                self.unique_prerequisites()
+                    .into_iter()
+                    .map(locate)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            ("+", located(&self.prerequisites)),
+            ("|", located(&self.order_only)),
+        ] {
+            // This `unwrap()` is safe because automatic variable names are always valid.
+            vars.set_with_origin(k.to_string(), v, false, Origin::Automatic)
+                .unwrap();
+        }
+
+        vars
+    }
 
-                // If we're just printing, we are done with this line.
-                if makefile.opts.just_print {
-                    continue;
+    /// Run the recipe to make `target`. If `output` is given, then the output of the commands is
+    /// collected there rather than written directly (e.g., when running jobs in parallel).
+    ///
+    /// If the recipe fails after changing the target file, then the file is deleted, so a partial
+    /// target isn't considered up to date by the next run, unless the target is `.PRECIOUS`.
+    pub fn execute<L: Logger>(
+        &self,
+        makefile: &Makefile<L>,
+        target: &str,
+        output: Option<&mut JobOutput>,
+    ) -> Result<(), MakeError> {
+        let before = file_mtime(target);
+        let result = self.run_recipe(makefile, target, output);
+
+        let rule_map = &makefile.rule_map;
+        if result.is_err() && !rule_map.is_precious(target) && !rule_map.is_phony(target) {
+            let after = file_mtime(target);
+            if after.is_some() && after != before {
+                makefile
+                    .logger
+                    .info(format!("Deleting file '{target}'."), None);
+                if let Err(e) = fs::remove_file(target) {
+                    makefile
+                        .logger
+                        .warn(format!("Could not delete file '{target}' ({e})."), None);
                 }
             }
+        }
 
-            // Execute the recipe line.
-            let res = Command::new(shell)
-                .args(&shell_flags)
-                .arg(line)
-                .status()
-                .map_err(|e| MakeError::new(e.to_string(), self.context.clone()))?;
+        result
+    }
 
-            // Check for command errors, unless directed to ignore them.
-            if command_modifier != Some('-') && !makefile.opts.ignore_errors {
-                if let Some(code) = res.code() {
-                    if code != 0 {
-                        return Err(MakeError::new(
-                            format!("Failed with code {}.", code),
-                            self.context.clone(),
-                        ));
-                    }
-                } else {
-                    return Err(MakeError::new("Killed.", self.context.clone()));
+    /// Run each command of the recipe to make `target` (see `execute`).
+    fn run_recipe<L: Logger>(
+        &self,
+        makefile: &Makefile<L>,
+        target: &str,
+        mut output: Option<&mut JobOutput>,
+    ) -> Result<(), MakeError> {
+        let vars = self.automatic_vars(makefile, target);
+        let env = makefile.expand_env(&vars);
+
+        // The shell and its flags may be recursively-expanded (e.g., `SHELL = $(SH)`).
+        let expand_var = |name: &str| {
+            env.expand(&format!("$({name})"))
+                .map_err(|e| MakeError::new(e, self.context.clone()))
+        };
+        let shell = &expand_var("SHELL")?;
+        let shell_flags = expand_var(".SHELLFLAGS")?;
+        let shell_flags = shell_flags.split_whitespace().collect::<Vec<_>>();
+
+        // Expand the recipe now that the automatic variables are known.
+        let recipe = self
+            .recipe
+            .iter()
+            .map(|line| {
+                env.expand(line)
+                    .map_err(|e| MakeError::new(e, self.context.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Under `-s`, or if the target is silenced by `.SILENT`, every command is treated as if it
+        // had the `@` modifier.
+        let silent = makefile.opts.silent || makefile.rule_map.is_silent(target);
+
+        // Under `.ONESHELL`, the entire recipe (including blank lines) is passed to a single shell.
+        // Only the first line is checked for command modifiers, but modifiers are stripped from
+        // every line since they would be meaningless to the shell.
+        if makefile.rule_map.has_target(".ONESHELL") {
+            let mut modifiers = recipe
+                .first()
+                .map(|line| command_modifiers(line).0)
+                .unwrap_or_default();
+            modifiers.silent |= silent;
+            modifiers.force |= self.recipe.iter().any(|line| references_make(line));
+            let script = recipe
+                .iter()
+                .map(|line| line.trim_start_matches(['@', '-', '+']))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            if script.trim().is_empty() {
+                return Ok(());
+            }
+
+            return self.run(makefile, shell, &shell_flags, &script, modifiers, output);
+        }
+
+        for (line, raw) in recipe.iter().zip(&self.recipe) {
+            // Blank lines are only meaningful under `.ONESHELL`.
+            if line.is_empty() {
+                continue;
+            }
+
+            let (mut modifiers, command) = command_modifiers(line);
+            modifiers.silent |= silent;
+            modifiers.force |= references_make(raw);
+            self.run(
+                makefile,
+                shell,
+                &shell_flags,
+                command,
+                modifiers,
+                output.as_deref_mut(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Echo and run a command in the shell, handling the effects of the command `modifiers`.
+    fn run<L: Logger>(
+        &self,
+        makefile: &Makefile<L>,
+        shell: &str,
+        shell_flags: &[&str],
+        command: &str,
+        modifiers: CommandModifiers,
+        mut output: Option<&mut JobOutput>,
+    ) -> Result<(), MakeError> {
+        // Apply the recipe transform, if any (e.g., to adapt commands for a non-POSIX shell).
+        let command = match makefile.recipe_transform {
+            Some(transform) => transform(command),
+            None => command.to_string(),
+        };
+
+        // Commands are only printed when just printing, unless they are forced to run.
+        let dry_run = makefile.opts.just_print && !modifiers.force;
+
+        // Echo the command to stdout, unless suppressed. Unlike `println!`, write errors (e.g., a
+        // broken pipe when piped to `head`) don't panic, since the echo is not needed to make the
+        // target.
+        if !modifiers.silent || dry_run {
+            let _ = match &mut output {
+                Some(output) => writeln!(output.stdout, "{}", command),
+                None if makefile.opts.capture_output => {
+                    makefile.logger.recipe_stdout(&command);
+                    Ok(())
                 }
+                None => writeln!(io::stdout(), "{}", command),
+            };
+        }
+
+        // If we're just printing, we are done with this command.
+        if dry_run {
+            return Ok(());
+        }
+
+        // Execute the command with any exported variables (and without unexported ones), passing
+        // on the flags (and any jobserver) to sub-makes.
+        let exported = makefile
+            .exported_env()
+            .map_err(|e| MakeError::new(e, self.context.clone()))?;
+        let mut cmd = Command::new(shell);
+        cmd.args(shell_flags).arg(&command).envs(exported);
+        for k in makefile.unexported_env() {
+            cmd.env_remove(k);
+        }
+        cmd.env("MAKEFLAGS", makefile.child_makeflags());
+        let res = match output {
+            Some(output) => cmd.output().map(|out| {
+                output.stdout.extend(out.stdout);
+                output.stderr.extend(out.stderr);
+                out.status
+            }),
+            None if makefile.opts.capture_output => cmd.output().map(|out| {
+                log_recipe_output(&*makefile.logger, &out.stdout, &out.stderr);
+                out.status
+            }),
+            None => cmd.status(),
+        }
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied => MakeError::new(
+                format!("SHELL '{}' not found or not executable.", shell),
+                self.context.clone(),
+            ),
+            _ => MakeError::new(e.to_string(), self.context.clone()),
+        })?;
+
+        // Check for command errors, unless directed to ignore them.
+        if !modifiers.ignore_errors && !makefile.opts.ignore_errors {
+            if let Some(code) = res.code() {
+                if code != 0 {
+                    return Err(MakeError::new(
+                        format!("Failed with code {}.", code),
+                        self.context.clone(),
+                    ));
+                }
+            } else {
+                return Err(MakeError::new("Killed.", self.context.clone()));
             }
         }
 
@@ -68,8 +284,182 @@ impl Rule {
     }
 }
 
+/// Pass captured recipe output to the logger, line by line.
+pub(crate) fn log_recipe_output<L: Logger>(logger: &L, stdout: &[u8], stderr: &[u8]) {
+    for line in String::from_utf8_lossy(stdout).lines() {
+        logger.recipe_stdout(line);
+    }
+    for line in String::from_utf8_lossy(stderr).lines() {
+        logger.recipe_stderr(line);
+    }
+}
+
+/// The effects of the command modifiers at the start of a recipe line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct CommandModifiers {
+    /// `@`: Don't echo the command.
+    silent: bool,
+
+    /// `-`: Ignore errors from the command.
+    ignore_errors: bool,
+
+    /// `+`: Run the command even when just printing.
+    force: bool,
+}
+
+/// Whether an (unexpanded) recipe line runs a sub-make via `$(MAKE)`, in which case it is run even
+/// when just printing, as if it had the `+` modifier, so the sub-make can print its own commands.
+fn references_make(line: &str) -> bool {
+    line.contains("$(MAKE)") || line.contains("${MAKE}")
+}
+
+/// Split the command modifiers from the start of a recipe line. Modifiers may be combined and
+/// repeated in any order, and separated by whitespace (e.g., `@-cmd` or `- @ cmd`).
+fn command_modifiers(line: &str) -> (CommandModifiers, &str) {
+    let mut modifiers = CommandModifiers::default();
+    let command = line.trim_start_matches(|c: char| {
+        match c {
+            '@' => modifiers.silent = true,
+            '-' => modifiers.ignore_errors = true,
+            '+' => modifiers.force = true,
+            c if c.is_whitespace() => {}
+            _ => return false,
+        }
+        true
+    });
+
+    (modifiers, command)
+}
+
+/// Whether a prerequisite with `prereq_mtime` requires remaking a target with `target_mtime`.
+///
+/// `mtime`s are compared with the full precision the filesystem provides (nanoseconds on most
+/// platforms), but coarse filesystems can give a prerequisite the same `mtime` as a target written
+/// just before it. Since we cannot tell which was written first, equal `mtime`s conservatively
+/// cause a rebuild.
+///
+/// Targets listed in `.LOW_RESOLUTION_TIME` are made by tools which truncate timestamps to whole
+/// seconds (e.g., `cp -p`), so both sides are compared at one-second resolution, and a prerequisite
+/// from the same second as the target doesn't cause a rebuild.
+fn is_out_of_date(
+    target_mtime: SystemTime,
+    prereq_mtime: SystemTime,
+    low_resolution: bool,
+) -> bool {
+    if low_resolution {
+        let secs = |t: SystemTime| {
+            t.duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+        };
+        return secs(prereq_mtime) > secs(target_mtime);
+    }
+
+    prereq_mtime >= target_mtime
+}
+
+/// The current `mtime` of a file involved in making a goal, and whether it is out of date.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Freshness {
+    pub mtime: Option<SystemTime>,
+    pub out_of_date: bool,
+}
+
+/// Why a rule's recipe needs to run, as logged under `--trace`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum RemakeReason {
+    /// All targets are remade (`-B`).
+    AlwaysMake,
+
+    /// The target doesn't exist.
+    Missing,
+
+    /// A prereq doesn't exist, so it will be made, which makes it newer than the target.
+    PrereqMissing(String),
+
+    /// A prereq is newer than the target.
+    PrereqNewer {
+        prereq: String,
+        prereq_mtime: SystemTime,
+        target_mtime: SystemTime,
+    },
+
+    /// Under `-j`, a prereq will be remade before this target.
+    PrereqPlanned(String),
+
+    /// Double-colon rules without prereqs always run.
+    DoubleColon,
+}
+
+/// Format an `mtime` as seconds since the epoch, with nanoseconds.
+fn format_mtime(mtime: SystemTime) -> String {
+    let d = mtime.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("{}.{:09}", d.as_secs(), d.subsec_nanos())
+}
+
+impl fmt::Display for RemakeReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlwaysMake => write!(f, "all targets are remade (-B)"),
+            Self::Missing => write!(f, "it does not exist"),
+            Self::PrereqMissing(prereq) => write!(f, "prerequisite '{prereq}' does not exist"),
+            Self::PrereqNewer {
+                prereq,
+                prereq_mtime,
+                target_mtime,
+            } => write!(
+                f,
+                "prerequisite '{prereq}' ({}) is newer than the target ({})",
+                format_mtime(*prereq_mtime),
+                format_mtime(*target_mtime)
+            ),
+            Self::PrereqPlanned(prereq) => write!(f, "prerequisite '{prereq}' will be remade"),
+            Self::DoubleColon => write!(f, "it has a double-colon rule without prerequisites"),
+        }
+    }
+}
+
+/// A target being made by `RuleMap::execute`, on its explicit stack of targets.
+struct Frame<'a> {
+    target: String,
+    rules: Vec<Cow<'a, Rule>>,
+    target_mtime: Option<SystemTime>,
+    low_resolution: bool,
+
+    /// The rule being checked (more than one only for double-colon rules), and the next of its
+    /// prereqs to check, counting the order-only prereqs after the normal prereqs.
+    rule: usize,
+    prereq: usize,
+
+    /// Why the current rule needs to run, if it does (so far).
+    reason: Option<RemakeReason>,
+
+    /// Under `keep_going`, a failed prereq doesn't stop the other prereqs from being made, but the
+    /// target itself will not be remade.
+    prereqs_failed: bool,
+
+    executed: bool,
+
+    /// The error from making the target, if it failed.
+    error: Option<MakeError>,
+}
+
+/// A target being checked by `RuleMap::freshness`, on its explicit stack of targets.
+struct FreshnessFrame {
+    target: String,
+    mtime: Option<SystemTime>,
+    out_of_date: bool,
+    low_resolution: bool,
+
+    /// The prereqs left to check (in reverse order), with whether each is order-only.
+    prereqs: Vec<(String, bool)>,
+
+    /// The prereq being checked, whose result is applied once it is known.
+    current: Option<(String, bool)>,
+}
+
 /// Wrapper for a mapping of targets to rules, and with a facility to execute targets.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct RuleMap {
     /// Storage for added rules. Rules MUST only be inserted, as removal may invalidate items in
     /// `by_target`.
@@ -77,6 +467,24 @@ pub struct RuleMap {
 
     /// Map targets (strings) to the rules which reference them by index into `self.rules`.
     by_target: HashMap<String, Vec<usize>>,
+
+    /// Pattern rules (e.g., `%.o: %.c`), by index into `self.rules`, in the order they were defined.
+    /// These are only used for targets which have no explicit rule.
+    patterns: Vec<usize>,
+
+    /// The `.DEFAULT` rule, by index into `self.rules`, whose recipe makes any target which has no
+    /// rule and doesn't exist.
+    default: Option<usize>,
+
+    /// Targets declared as prerequisites of `.PHONY`, which are always considered out of date.
+    phony: HashSet<String>,
+
+    /// Targets declared as prerequisites of `.INTERMEDIATE`, which are removed after the run if
+    /// they were created by it.
+    intermediate: HashSet<String>,
+
+    /// Targets declared as prerequisites of `.PRECIOUS`, which are kept even if their recipe fails.
+    precious: HashSet<String>,
 }
 
 /// Note that methods on `RuleMap` MUST ensure that only new entries are added to either `rules` or
@@ -87,16 +495,71 @@ impl RuleMap {
         Self {
             rules: vec![],
             by_target: HashMap::new(),
+            patterns: vec![],
+            default: None,
+            phony: HashSet::new(),
+            intermediate: HashSet::new(),
+            precious: HashSet::new(),
         }
     }
 
     /// Insert a rule, update the `by_target` hashmap, and validate the rule.
-    pub fn insert<L: Logger>(&mut self, rule: Rule, logger: &Box<L>) -> Result<(), MakeError> {
+    pub fn insert<L: Logger>(&mut self, rule: Rule, logger: &L) -> Result<(), MakeError> {
+        // `.PHONY`, `.INTERMEDIATE`, and `.PRECIOUS` are not buildable targets, they just declare
+        // which targets are phony, intermediate, or precious. A bare `.INTERMEDIATE` or `.PRECIOUS`
+        // has no effect.
+        for (special, set) in [
+            (".PHONY", &mut self.phony),
+            (".INTERMEDIATE", &mut self.intermediate),
+            (".PRECIOUS", &mut self.precious),
+        ] {
+            if rule.targets.iter().any(|t| t == special) {
+                if rule.targets.len() > 1 {
+                    return Err(MakeError::new(
+                        format!("Cannot define `{special}` together with other targets."),
+                        rule.context.clone(),
+                    ));
+                }
+
+                set.extend(rule.prerequisites);
+                return Ok(());
+            }
+        }
+
+        // `.DEFAULT` is stored separately, since it's only used for targets without any rule. A
+        // later definition replaces an earlier one.
+        if rule.targets.iter().any(|t| t == ".DEFAULT") {
+            if rule.targets.len() > 1 {
+                return Err(MakeError::new(
+                    "Cannot define `.DEFAULT` together with other targets.",
+                    rule.context.clone(),
+                ));
+            }
+
+            self.default = Some(self.rules.len());
+            self.rules.push(rule);
+            return Ok(());
+        }
+
         // Load rule into the storage vector and get a reference to it and the insertion index.
         let index = self.rules.len();
         self.rules.push(rule);
         let rule = self.rules.last().unwrap();
 
+        // Pattern rules are stored separately, since they are matched against targets rather than
+        // looked up directly.
+        if rule.targets.iter().any(|t| t.contains('%')) {
+            if !rule.targets.iter().all(|t| t.contains('%')) {
+                return Err(MakeError::new(
+                    "Cannot mix pattern and explicit targets in the same rule.",
+                    rule.context.clone(),
+                ));
+            }
+
+            self.patterns.push(index);
+            return Ok(());
+        }
+
         // Load each target into `by_target` hashmap and catch some basic validation errors.
         for target in &rule.targets {
             match self.by_target.get_mut(target) {
@@ -117,10 +580,7 @@ impl RuleMap {
                     if rule.double_colon {
                         rule_indices.push(index);
                     } else {
-                        logger.warn(
-                            "Ignoring duplicate definition.".to_string(),
-                            Some(&rule.context),
-                        );
+                        logger.warn("Ignoring duplicate definition.", Some(&rule.context));
                     }
                 }
             }
@@ -129,72 +589,638 @@ impl RuleMap {
         Ok(())
     }
 
+    /// Get the rules which make `target` (more than one only for double-colon rules).
+    pub fn get(&self, target: &str) -> Vec<&Rule> {
+        self.by_target
+            .get(target)
+            .map(|indices| indices.iter().map(|&i| &self.rules[i]).collect())
+            .unwrap_or_default()
+    }
+
+    /// The explicit targets (i.e., not patterns) in the order they were first defined. This
+    /// includes special targets stored as rules, such as `.SILENT`, but not `.PHONY`.
+    pub fn targets(&self) -> Vec<&str> {
+        let mut seen = HashSet::new();
+        self.rules
+            .iter()
+            .flat_map(|r| &r.targets)
+            .map(String::as_str)
+            .filter(|t| self.by_target.contains_key(*t) && seen.insert(*t))
+            .collect()
+    }
+
+    /// The pattern rules (e.g., `%.o: %.c`), in the order they were defined.
+    pub fn pattern_rules(&self) -> Vec<&Rule> {
+        self.patterns.iter().map(|&i| &self.rules[i]).collect()
+    }
+
+    /// Get every rule which could make `target`, along with its context, for tools such as editors
+    /// (e.g., to go to where a target is defined). These are the explicit rules for `target` (more
+    /// than one only for double-colon rules), or if there are none, each pattern rule whose target
+    /// pattern matches, as defined (i.e., without the stem substituted or checking that its
+    /// prerequisites can be made).
+    pub fn rules_for(&self, target: &str) -> Vec<&Rule> {
+        let rules = self.get(target);
+        if !rules.is_empty() {
+            return rules;
+        }
+
+        self.patterns
+            .iter()
+            .map(|&i| &self.rules[i])
+            .filter(|r| r.targets.iter().any(|t| pattern_stem(t, target).is_some()))
+            .collect()
+    }
+
+    /// Get the rules which make `target`. If there is no explicit rule, then the first pattern rule
+    /// whose target pattern matches (and whose prerequisites exist or can be made) is used, with the
+    /// stem substituted into its prerequisites.
+    fn resolve<L: Logger>(
+        &self,
+        makefile: &Makefile<L>,
+        target: &str,
+        ctx: &mut ExecContext,
+    ) -> Vec<Cow<'_, Rule>> {
+        makefile.debug_verbose(format!("Looking for a rule for '{target}'."));
+        if let Some(indices) = self.by_target.get(target) {
+            return indices
+                .iter()
+                .map(|&i| Cow::Borrowed(&self.rules[i]))
+                .collect();
+        }
+
+        self.pattern_rule(makefile, target, ctx)
+            .or_else(|| self.default_rule(makefile, target, ctx))
+            .map(Cow::Owned)
+            .into_iter()
+            .collect()
+    }
+
+    /// Use the `.DEFAULT` recipe to make `target`, if it doesn't exist. Any prerequisites of
+    /// `.DEFAULT` are ignored.
+    fn default_rule<L: Logger>(
+        &self,
+        makefile: &Makefile<L>,
+        target: &str,
+        ctx: &mut ExecContext,
+    ) -> Option<Rule> {
+        let rule = &self.rules[self.default?];
+        if ctx.mtime(makefile, &target.to_string()).is_some() {
+            return None;
+        }
+
+        makefile.debug_verbose(format!("Using the `.DEFAULT` recipe for '{target}'."));
+        Some(Rule {
+            targets: vec![target.to_string()],
+            prerequisites: vec![],
+            order_only: vec![],
+            recipe: rule.recipe.clone(),
+            context: rule.context.clone(),
+            double_colon: false,
+        })
+    }
+
+    /// Find a pattern rule which can make `target`, and make it concrete for that target.
+    fn pattern_rule<L: Logger>(
+        &self,
+        makefile: &Makefile<L>,
+        target: &str,
+        ctx: &mut ExecContext,
+    ) -> Option<Rule> {
+        for &i in &self.patterns {
+            let rule = &self.rules[i];
+            let Some(stem) = rule.targets.iter().find_map(|t| pattern_stem(t, target)) else {
+                continue;
+            };
+            makefile.debug_verbose(format!("Trying pattern rule with stem '{stem}'."));
+
+            let substitute = |prereqs: &[String]| -> Vec<String> {
+                prereqs.iter().map(|p| substitute_stem(p, stem)).collect()
+            };
+            let prerequisites = substitute(&rule.prerequisites);
+
+            // Pattern rules are not chained, so each prerequisite must exist or have a rule.
+            let viable = prerequisites
+                .iter()
+                .all(|p| self.has_target(p) || ctx.mtime(makefile, p).is_some());
+            if !viable {
+                continue;
+            }
+
+            return Some(Rule {
+                targets: vec![target.to_string()],
+                prerequisites,
+                order_only: substitute(&rule.order_only),
+                recipe: rule.recipe.clone(),
+                context: rule.context.clone(),
+                double_colon: rule.double_colon,
+            });
+        }
+
+        None
+    }
+
+    /// Whether `target` is listed in `.LOW_RESOLUTION_TIME`.
+    fn is_low_resolution(&self, target: &str) -> bool {
+        self.get(".LOW_RESOLUTION_TIME")
+            .iter()
+            .any(|r| r.prerequisites.iter().any(|p| p == target))
+    }
+
+    /// Whether the recipe for `target` is silenced by `.SILENT`, either because it is listed, or
+    /// because `.SILENT` has no prerequisites (which silences every recipe).
+    pub fn is_silent(&self, target: &str) -> bool {
+        self.get(".SILENT")
+            .iter()
+            .any(|r| r.prerequisites.is_empty() || r.prerequisites.iter().any(|p| p == target))
+    }
+
+    /// Whether `target` is listed in `.PHONY`.
+    pub fn is_phony(&self, target: &str) -> bool {
+        self.phony.contains(target)
+    }
+
+    /// Whether `target` is listed in `.INTERMEDIATE`.
+    pub fn is_intermediate(&self, target: &str) -> bool {
+        self.intermediate.contains(target)
+    }
+
+    /// Whether `target` is listed in `.PRECIOUS`.
+    pub fn is_precious(&self, target: &str) -> bool {
+        self.precious.contains(target)
+    }
+
+    /// Whether no rules (explicit or pattern) have been defined. Special targets such as `.PHONY`
+    /// don't count.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Whether any rule makes `target`.
+    pub fn has_target(&self, target: &str) -> bool {
+        self.by_target.contains_key(target)
+    }
+
+    /// The error for a target which could not be remade because of an earlier error.
+    pub(crate) fn not_remade(target: &str) -> MakeError {
+        MakeError::new(
+            format!("Target '{}' not remade because of errors.", target),
+            Context::new(),
+        )
+    }
+
+    /// Under `keep_going`, errors are logged where they happen, and are replaced with an error
+    /// noting that the target was not remade. That way, any targets which depend on this one will
+    /// fail without logging the original error again.
+    fn keep_going_error<L: Logger>(
+        makefile: &Makefile<L>,
+        target: &str,
+        e: MakeError,
+    ) -> MakeError {
+        if !makefile.opts.keep_going {
+            return e;
+        }
+
+        makefile.logger.error(e.msg, Some(&e.context));
+        Self::not_remade(target)
+    }
+
     /// Execute the rules for a particular target, checking prerequisites.
+    ///
+    /// The dependency graph is traversed with an explicit stack of [`Frame`]s rather than by
+    /// recursion, so a very deep chain of prerequisites can't overflow the native stack. Each frame
+    /// checks its target's prerequisites in order, and when one needs to be made, a frame for it is
+    /// pushed. Once that frame is done, its result is handed back to the frame below it.
     pub fn execute<L: Logger>(
         &self,
         makefile: &Makefile<L>,
-        target: &String,
+        target: &str,
+        ctx: &mut ExecContext,
     ) -> Result<(), MakeError> {
-        let rule_indices = self.by_target.get(target).ok_or_else(|| {
-            MakeError::new(
-                format!("No rule to make target '{}'.", target),
-                Context::new(),
-            )
-        })?;
-        let target_mtime_opt = makefile.get_mtime(target);
+        let mut stack: Vec<Frame> = vec![];
+        let mut next = Some(target.to_string());
+
+        loop {
+            // The result of a target which is done, to be handed to the frame below it.
+            let mut result = None;
+
+            if let Some(target) = next.take() {
+                match self.enter(makefile, target, ctx) {
+                    Ok(frame) => stack.push(frame),
+                    Err(r) => result = Some(r),
+                }
+            } else {
+                // This `unwrap()` is safe because there is always a frame when no target is next.
+                let frame = stack.last_mut().unwrap();
+                match self.step(makefile, frame, ctx) {
+                    Some(prereq) => next = Some(prereq),
+                    None => {
+                        let frame = stack.pop().unwrap();
+                        let r = frame.error.map_or(Ok(()), Err);
+                        result = Some(Self::finish(frame.target, r, ctx));
+                    }
+                }
+            }
+
+            // A failed prereq doesn't stop the other prereqs from being made under `keep_going`,
+            // but otherwise the target fails with the same error.
+            while let Some(r) = result.take() {
+                let Some(frame) = stack.last_mut() else {
+                    return r;
+                };
+
+                match r {
+                    Ok(()) => {}
+                    Err(_) if makefile.opts.keep_going => frame.prereqs_failed = true,
+                    Err(e) => {
+                        let frame = stack.pop().unwrap();
+                        result = Some(Self::finish(frame.target, Err(e), ctx));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Start making `target`, returning a frame to check its prerequisites, or the result if there
+    /// is nothing else to check.
+    fn enter<'a, L: Logger>(
+        &'a self,
+        makefile: &Makefile<L>,
+        target: String,
+        ctx: &mut ExecContext,
+    ) -> Result<Frame<'a>, Result<(), MakeError>> {
+        // A target which was already made (e.g., a shared prereq) is only made once, even under
+        // `always_make`. Under `-j`, the same goes for a target which is already planned.
+        if ctx.made.contains(&target) || ctx.is_planned(&target) {
+            return Err(Ok(()));
+        }
+        if ctx.failed.contains(&target) || ctx.skipped.contains(&target) {
+            return Err(Err(Self::not_remade(&target)));
+        }
+
+        // If this target is already being made further up the dependency chain, then this is a
+        // circular dependency, so drop it.
+        if ctx.in_progress.contains(&target) {
+            makefile
+                .logger
+                .warn(format!("Circular dependency on '{target}' dropped."), None);
+            return Err(Ok(()));
+        }
+        ctx.in_progress.insert(target.clone());
+
+        let rules = self.resolve(makefile, &target, ctx);
+        if rules.is_empty() {
+            let e = Self::keep_going_error(
+                makefile,
+                &target,
+                MakeError::new(
+                    format!("No rule to make target '{}'.", target),
+                    Context::new(),
+                ),
+            );
+            return Err(Self::finish(target, Err(e), ctx));
+        }
 
         // Old files have their rules ignored.
-        if makefile.opts.old_file.contains(target) {
+        if makefile.opts.old_file.contains(&target) {
             makefile.logger.info(
                 format!("Target '{target}' is up to date (old)."),
-                Some(&Context::new()),
+                Some(&rules[0].context),
             );
-            return Ok(());
+            return Err(Self::finish(target, Ok(()), ctx));
         }
 
-        let mut executed = false;
-        for i in rule_indices {
-            let rule = &self.rules[i.to_owned()];
-            let mut should_execute = makefile.opts.always_make;
+        Ok(Frame {
+            target_mtime: ctx.mtime(makefile, &target),
+            low_resolution: self.is_low_resolution(&target),
+            target,
+            rules,
+            rule: 0,
+            prereq: 0,
+            reason: makefile
+                .opts
+                .always_make
+                .then_some(RemakeReason::AlwaysMake),
+            prereqs_failed: false,
+            executed: false,
+            error: None,
+        })
+    }
 
-            // Check (and possibly execute) prereqs.
-            for prereq in &rule.prerequisites {
+    /// Check the prerequisites of the frame's target, running its rules as their prerequisites are
+    /// checked. Returns the next prereq which needs to be made first, or `None` once the target is
+    /// done (with any error left in `frame.error`).
+    fn step<L: Logger>(
+        &self,
+        makefile: &Makefile<L>,
+        frame: &mut Frame,
+        ctx: &mut ExecContext,
+    ) -> Option<String> {
+        let target = &frame.target;
+
+        while let Some(rule) = frame.rules.get(frame.rule) {
+            // Check (and possibly make) prereqs, followed by order-only prereqs.
+            let i = frame.prereq;
+            frame.prereq += 1;
+            if let Some(prereq) = rule.prerequisites.get(i) {
                 // Check if prereq exists unless `always_make`.
                 if makefile.opts.always_make {
-                    self.execute(makefile, prereq)?;
+                    return Some(prereq.clone());
+                } else if ctx.is_planned(prereq) {
+                    // Under `-j`, the prereq will be remade before this target, so it will be more
+                    // up-to-date than the target.
+                    frame
+                        .reason
+                        .get_or_insert_with(|| RemakeReason::PrereqPlanned(prereq.clone()));
                 } else {
-                    match makefile.get_mtime(prereq) {
+                    match ctx.mtime(makefile, prereq) {
                         None => {
                             // Prereq doesn't exist, so make it. By definition, it's more up-to-date
                             // than the target.
-                            self.execute(makefile, prereq)?;
-                            should_execute = true;
+                            frame
+                                .reason
+                                .get_or_insert_with(|| RemakeReason::PrereqMissing(prereq.clone()));
+                            return Some(prereq.clone());
                         }
                         Some(prereq_mtime) => {
                             // Prereq exists, so check if it's more up-to-date than the target.
-                            if let Some(target_mtime) = target_mtime_opt {
-                                if prereq_mtime > target_mtime {
-                                    should_execute = true;
+                            if let Some(target_mtime) = frame.target_mtime {
+                                if is_out_of_date(target_mtime, prereq_mtime, frame.low_resolution)
+                                {
+                                    frame
+                                        .reason
+                                        .get_or_insert_with(|| RemakeReason::PrereqNewer {
+                                            prereq: prereq.clone(),
+                                            prereq_mtime,
+                                            target_mtime,
+                                        });
                                 }
                             }
                         }
                     }
                 }
+                continue;
             }
 
-            if target_mtime_opt.is_none() || should_execute {
-                rule.execute(makefile)?;
-                executed = true;
+            // Order-only prereqs are made if they don't exist, but are never compared to the target.
+            if let Some(prereq) = rule.order_only.get(i - rule.prerequisites.len()) {
+                if makefile.opts.always_make || ctx.mtime(makefile, prereq).is_none() {
+                    return Some(prereq.clone());
+                }
+                continue;
             }
+
+            // Failed prereqs were already logged, so just note that this target was not remade.
+            if frame.prereqs_failed {
+                ctx.skipped.push(target.clone());
+                frame.error = Some(Self::not_remade(target));
+                return None;
+            }
+
+            // Each double-colon rule is checked against its own prereqs (since `reason` is reset
+            // for each rule), and one without prereqs always runs.
+            let reason = match frame.reason.take() {
+                _ if frame.target_mtime.is_none() => Some(RemakeReason::Missing),
+                None if rule.double_colon && rule.prerequisites.is_empty() => {
+                    Some(RemakeReason::DoubleColon)
+                }
+                reason => reason,
+            };
+            if let Some(reason) = reason {
+                if makefile.opts.trace {
+                    makefile.logger.trace(
+                        format!("Remaking '{target}' because {reason}."),
+                        Some(&rule.context),
+                    );
+                }
+
+                ctx.out_of_date = true;
+                match &mut ctx.plan {
+                    // Under `question`, the recipe is never run, only noted as out of date.
+                    _ if makefile.opts.question => {}
+                    Some(plan) => plan.add(target, rule.clone().into_owned()),
+                    None => {
+                        let before = file_mtime(target);
+                        if let Err(e) = rule.execute(makefile, target, None) {
+                            frame.error = Some(Self::keep_going_error(makefile, target, e));
+                            return None;
+                        }
+                        ctx.invalidate(target);
+                        ctx.record_change(target, before);
+                    }
+                }
+                frame.executed = true;
+            }
+
+            // Move on to the next rule (only for double-colon rules).
+            frame.rule += 1;
+            frame.prereq = 0;
+            frame.reason = makefile
+                .opts
+                .always_make
+                .then_some(RemakeReason::AlwaysMake);
         }
 
-        if !executed {
+        if !frame.executed {
             makefile.logger.info(
                 format!("Target '{target}' is up to date."),
-                Some(&Context::new()),
+                Some(&frame.rules[0].context),
             );
         }
 
-        Ok(())
+        None
+    }
+
+    /// Finish making `target`, recording it as failed if it failed (rather than being skipped
+    /// because of a failed prereq).
+    fn finish(
+        target: String,
+        result: Result<(), MakeError>,
+        ctx: &mut ExecContext,
+    ) -> Result<(), MakeError> {
+        ctx.in_progress.remove(&target);
+        match result {
+            Ok(()) => {
+                ctx.made.insert(target);
+            }
+            Err(_) if !ctx.skipped.contains(&target) => ctx.failed.push(target),
+            Err(_) => {}
+        }
+
+        result
+    }
+
+    /// Determine whether `target` and (transitively) its prerequisites are out of date, without
+    /// executing anything, recording each in `freshness`. Returns whether `target` is out of date.
+    /// Like `execute`, this walks an explicit stack of targets rather than recursing, and only
+    /// descends into the prerequisites which `execute` would make.
+    pub fn freshness<L: Logger>(
+        &self,
+        makefile: &Makefile<L>,
+        target: &str,
+        ctx: &mut ExecContext,
+        freshness: &mut HashMap<String, Freshness>,
+    ) -> bool {
+        let mut stack: Vec<FreshnessFrame> = vec![];
+        let mut next = Some(target.to_string());
+
+        // Whether the target checked last is out of date, for the frame which is waiting on it.
+        let mut done = None;
+
+        loop {
+            if let Some(target) = next.take() {
+                if let Some(f) = freshness.get(&target) {
+                    done = Some(f.out_of_date);
+                } else if !ctx.in_progress.insert(target.clone()) {
+                    // Circular dependencies are dropped, as when executing.
+                    done = Some(false);
+                } else {
+                    let mtime = ctx.mtime(makefile, &target);
+                    let mut frame = FreshnessFrame {
+                        out_of_date: makefile.opts.always_make || mtime.is_none(),
+                        low_resolution: self.is_low_resolution(&target),
+                        target,
+                        mtime,
+                        prereqs: vec![],
+                        current: None,
+                    };
+
+                    // Old files have their rules ignored.
+                    if makefile.opts.old_file.contains(&frame.target) {
+                        frame.out_of_date = false;
+                    } else {
+                        for rule in self.resolve(makefile, &frame.target, ctx) {
+                            // A double-colon rule without prereqs always runs.
+                            frame.out_of_date |= rule.double_colon && rule.prerequisites.is_empty();
+
+                            let prereqs = rule.prerequisites.iter().map(|p| (p.clone(), false));
+                            let order_only = rule.order_only.iter().map(|p| (p.clone(), true));
+                            frame.prereqs.extend(prereqs.chain(order_only));
+                        }
+                    }
+
+                    // Prereqs are popped off the end, so reverse them to check them in order.
+                    frame.prereqs.reverse();
+                    stack.push(frame);
+                }
+            }
+
+            let Some(frame) = stack.last_mut() else {
+                return done.unwrap_or(false);
+            };
+
+            // Apply the prereq which was just checked. Order-only prerequisites are involved, but
+            // never make the target out of date.
+            if let (Some((prereq, false)), Some(prereq_out_of_date)) =
+                (frame.current.take(), done.take())
+            {
+                // A prerequisite which would be remade makes the target out of date.
+                if prereq_out_of_date {
+                    frame.out_of_date = true;
+                } else if let (Some(target_mtime), Some(prereq_mtime)) =
+                    (frame.mtime, ctx.mtime(makefile, &prereq))
+                {
+                    frame.out_of_date |=
+                        is_out_of_date(target_mtime, prereq_mtime, frame.low_resolution);
+                }
+            }
+
+            match frame.prereqs.pop() {
+                Some(prereq) => {
+                    // As when executing, a prereq is only made if it doesn't exist (or under
+                    // `always_make`), so an existing prereq is only compared to the target.
+                    let mtime = ctx.mtime(makefile, &prereq.0);
+                    if makefile.opts.always_make || mtime.is_none() {
+                        next = Some(prereq.0.clone());
+                    } else {
+                        let f = freshness.entry(prereq.0.clone()).or_insert(Freshness {
+                            mtime,
+                            out_of_date: false,
+                        });
+                        done = Some(f.out_of_date);
+                    }
+                    frame.current = Some(prereq);
+                }
+                None => {
+                    // This `unwrap()` is safe because `frame` is the top of the stack.
+                    let frame = stack.pop().unwrap();
+                    ctx.in_progress.remove(&frame.target);
+                    done = Some(frame.out_of_date);
+                    freshness.insert(
+                        frame.target,
+                        Freshness {
+                            mtime: frame.mtime,
+                            out_of_date: frame.out_of_date,
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_command_modifiers() {
+        let (m, command) = command_modifiers("@-echo a");
+        assert!(m.silent && m.ignore_errors && !m.force);
+        assert_eq!(command, "echo a");
+
+        let (m, command) = command_modifiers("- @@ +echo -a");
+        assert!(m.silent && m.ignore_errors && m.force);
+        assert_eq!(command, "echo -a");
+
+        assert_eq!(
+            command_modifiers("echo @"),
+            (CommandModifiers::default(), "echo @")
+        );
+    }
+
+    #[test]
+    fn test_is_out_of_date() {
+        let t = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let ns = Duration::from_nanos(1);
+        assert!(is_out_of_date(t, t + ns, false));
+        assert!(is_out_of_date(t, t, false));
+        assert!(!is_out_of_date(t + ns, t, false));
+    }
+
+    #[test]
+    fn test_is_out_of_date_low_resolution() {
+        let t = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let half = Duration::from_millis(500);
+        assert!(!is_out_of_date(t, t + half, true));
+        assert!(!is_out_of_date(t, t, true));
+        assert!(is_out_of_date(t, t + half * 2, true));
+        assert!(is_out_of_date(t, t + half, false));
+    }
+
+    #[test]
+    fn test_rules_for() {
+        let content = "all:: a\n\techo 1\n\nall:: b\n\techo 2\n\n%.o: %.c\n\tcc $<\n%.a: %.o\n";
+        let makefile = Makefile::from_reader(
+            content.as_bytes(),
+            Default::default(),
+            Box::new(crate::logger::BufferLogger::default()),
+            Vars::new([]),
+        )
+        .unwrap();
+
+        let rules = makefile.rule_map.rules_for("all");
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].prerequisites, vec!["a"]);
+        assert_eq!(rules[0].context.line_index, Some(0));
+        assert_eq!(rules[1].prerequisites, vec!["b"]);
+        assert_eq!(rules[1].context.line_index, Some(3));
+
+        let rules = makefile.rule_map.rules_for("x.o");
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].targets, vec!["%.o"]);
+        assert_eq!(rules[0].context.line_index, Some(6));
+        assert!(makefile.rule_map.rules_for("x.c").is_empty());
     }
 }
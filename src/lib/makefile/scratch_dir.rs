@@ -0,0 +1,72 @@
+//! A per-invocation scratch directory for temporary files.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A uniquely-named temporary directory (e.g., for response files), so temporary files from
+/// concurrent invocations cannot collide. The directory and its contents are removed on drop.
+#[derive(Debug)]
+pub struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    /// Create a new scratch directory inside the system temporary directory.
+    pub fn new() -> io::Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+
+        // The process ID makes the name unique between concurrent invocations, and the timestamp
+        // avoids reusing a stale directory from a process which had the same ID. Retry in the
+        // unlikely case that the name is already taken.
+        for attempt in 0..100 {
+            let path = std::env::temp_dir().join(format!(
+                "omake-{}-{}",
+                std::process::id(),
+                nanos.wrapping_add(attempt)
+            ));
+
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(Self { path }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "could not find an unused name",
+        ))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scratch_dir() {
+        let a = ScratchDir::new().unwrap();
+        let b = ScratchDir::new().unwrap();
+        assert_ne!(a.path(), b.path());
+
+        let path = a.path().to_path_buf();
+        fs::write(path.join("file"), "content").unwrap();
+        drop(a);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,49 @@
+//! A stand-in for the jobserver on platforms without Unix pipes. A jobserver can never be set up,
+//! so `-j` falls back to running jobs one at a time (with a warning).
+
+use std::io;
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// A handle on a pool of job tokens, which can't exist on this platform.
+#[derive(Debug)]
+pub enum Jobserver {}
+
+/// A token taken from the pool.
+#[derive(Debug)]
+pub struct Token<'a>(PhantomData<&'a Jobserver>);
+
+/// The error for any attempt to set up a jobserver.
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "jobserver is only supported on Unix",
+    )
+}
+
+impl Jobserver {
+    /// Create a jobserver shared through a named pipe (unsupported).
+    pub fn fifo(_jobs: usize, _dir: &Path) -> io::Result<Self> {
+        Err(unsupported())
+    }
+
+    /// Create a jobserver shared through an anonymous pipe (unsupported).
+    pub fn pipe(_jobs: usize) -> io::Result<Self> {
+        Err(unsupported())
+    }
+
+    /// Connect to the pool of a parent `make` (unsupported).
+    pub fn from_auth(_auth: &str) -> io::Result<Self> {
+        Err(unsupported())
+    }
+
+    /// The value for `--jobserver-auth` in `MAKEFLAGS`.
+    pub fn auth(&self) -> &str {
+        match *self {}
+    }
+
+    /// Take a token from the pool if one is available right now.
+    pub fn try_acquire(&self) -> io::Result<Option<Token<'_>>> {
+        match *self {}
+    }
+}
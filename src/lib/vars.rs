@@ -14,10 +14,17 @@ pub const DEFAULT_SUFFIXES: [&str; 13] = [
     ".C", ".F", ".S", ".c", ".cc", ".cpp", ".def", ".f", ".m", ".mod", ".p", ".r", ".s",
 ];
 
+/// Automatic variables, which are only bound while executing a rule's recipe.
+pub const AUTOMATIC_VARS: [char; 5] = ['@', '<', '^', '+', '|'];
+
 /// List of variables where setting the value to blank means to reset it to the default value. All
 /// of these values MUST exist in [`DEFAULT_VARS`].
 pub const BLANK_MEANS_DEFAULT_VARS: [&str; 1] = [".RECIPEPREFIX"];
 
+/// Default variables which are needed to execute recipes at all, so they are kept even when the
+/// builtin variables are disabled (`-R`).
+pub const ESSENTIAL_VARS: [&str; 3] = [".RECIPEPREFIX", ".SHELLFLAGS", "SHELL"];
+
 /// Variables which are set in a non-recursive context by default, and can be overridden by the
 /// environment. `SHELL` is not included, since it cannot be overridden by the environment,
 /// unless explicitly directed to by `-e`.
@@ -96,16 +103,40 @@ pub const DEFAULT_RECURSIVE_VARS: [(&str, &str); 36] = [
 /// Represents the "raw" environment coming from the OS.
 pub type Env = HashMap<String, String>;
 
-/// A single variable, with a value and a flag indicating whether it is recursive.
-#[derive(Debug)]
+/// Where a variable's value came from, as reported by `$(origin)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Origin {
+    Undefined,
+    Default,
+    Environment,
+    File,
+    Automatic,
+}
+
+impl Origin {
+    /// The name of this origin, as reported by `$(origin)`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Undefined => "undefined",
+            Self::Default => "default",
+            Self::Environment => "environment",
+            Self::File => "file",
+            Self::Automatic => "automatic",
+        }
+    }
+}
+
+/// A single variable, with a value, a flag indicating whether it is recursive, and its origin.
+#[derive(Clone, Debug)]
 pub struct Var {
     pub value: String,
     pub recursive: bool,
+    pub origin: Origin,
 }
 
 /// Wrap a [`HashMap`] and a default `blank` value, providing an easy way to get variables, handling
 /// special and automatic variables properly.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Vars {
     map: HashMap<String, Var>,
 
@@ -127,28 +158,31 @@ impl Vars {
             blank: Var {
                 value: "".to_string(),
                 recursive: false,
+                origin: Origin::Undefined,
             },
             default_vars: HashMap::new(),
         };
 
         // Set default vars.
         for (k, v) in DEFAULT_VARS {
-            vars.set(k, v, false).unwrap();
+            vars.set_with_origin(k, v, false, Origin::Default).unwrap();
             vars.default_vars.insert(k.to_string(), v.to_string());
         }
 
         // Set default recursive vars.
         for (k, v) in DEFAULT_RECURSIVE_VARS {
-            vars.set(k, v, true).unwrap();
+            vars.set_with_origin(k, v, true, Origin::Default).unwrap();
         }
 
         // Set `SHELL` to `/bin/sh` by default.
-        vars.set("SHELL", "/bin/sh", false).unwrap();
+        vars.set_with_origin("SHELL", "/bin/sh", false, Origin::Default)
+            .unwrap();
 
         // Set default `SUFFIXES` and `.SUFFIXES`.
-        vars.set("SUFFIXES", &DEFAULT_SUFFIXES.join(" "), false)
+        let suffixes = DEFAULT_SUFFIXES.join(" ");
+        vars.set_with_origin("SUFFIXES", &suffixes, false, Origin::Default)
             .unwrap();
-        vars.set(".SUFFIXES", &DEFAULT_SUFFIXES.join(" "), false)
+        vars.set_with_origin(".SUFFIXES", &suffixes, false, Origin::Default)
             .unwrap();
 
         // Use `set` to initialize data.
@@ -159,6 +193,21 @@ impl Vars {
         vars
     }
 
+    /// Remove the builtin variables (e.g., `CC`), except for the [`ESSENTIAL_VARS`]. Variables
+    /// whose values differ from the builtin value (e.g., because they were overridden by the
+    /// environment) are kept.
+    pub fn clear_builtins(&mut self) {
+        for (k, v) in DEFAULT_VARS.iter().chain(DEFAULT_RECURSIVE_VARS.iter()) {
+            if ESSENTIAL_VARS.contains(k) {
+                continue;
+            }
+
+            if self.map.get(*k).is_some_and(|var| var.value == *v) {
+                self.map.remove(*k);
+            }
+        }
+    }
+
     /// Public interface for getting variables. For unknown keys, the `blank` object is returned. We
     /// should try to keep this interface as fast/simple as possible since it's used far more often
     /// than `set` (e.g., used for each line to check for recipe prefix).
@@ -171,12 +220,26 @@ impl Vars {
         }
     }
 
-    /// Public interface for setting variables.
-    pub fn set<S: Into<String>>(&mut self, k: S, v: S, recursive: bool) -> Result<(), String> {
-        let k = k.into().trim().to_string();
-        let mut v = v.into();
+    /// Get a variable only if it is defined (even if its value is empty), unlike `get`, which returns
+    /// a blank variable for unknown keys. This is the source of truth for whether a variable is
+    /// defined (e.g., for `?=` and `$(origin)`).
+    pub fn lookup(&self, k: impl AsRef<str>) -> Option<&Var> {
+        self.map.get(k.as_ref().trim())
+    }
 
-        // Do not insert bad variable names.
+    /// Iterate over the defined variables (in no particular order), e.g., to print them.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Var)> {
+        self.map.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Whether a variable is set in the sense of `ifdef`, meaning it has a non-empty value. The value
+    /// is not expanded, so `X = $(EMPTY)` is set.
+    pub fn is_set(&self, k: impl AsRef<str>) -> bool {
+        self.lookup(k).is_some_and(|var| !var.value.is_empty())
+    }
+
+    /// Check that a (trimmed) variable name doesn't contain whitespace or bad characters.
+    pub fn validate_name(k: &str) -> Result<(), String> {
         for ch in k.chars() {
             if ch.is_whitespace() {
                 return Err("Variable contains whitespace.".to_string());
@@ -187,6 +250,28 @@ impl Vars {
             }
         }
 
+        Ok(())
+    }
+
+    /// Public interface for setting variables (e.g., from a makefile).
+    pub fn set<S: Into<String>>(&mut self, k: S, v: S, recursive: bool) -> Result<(), String> {
+        self.set_with_origin(k, v, recursive, Origin::File)
+    }
+
+    /// Set a variable which came from somewhere other than a makefile.
+    pub fn set_with_origin<S: Into<String>>(
+        &mut self,
+        k: S,
+        v: S,
+        recursive: bool,
+        origin: Origin,
+    ) -> Result<(), String> {
+        let k = k.into().trim().to_string();
+        let mut v = v.into();
+
+        // Do not insert bad variable names.
+        Self::validate_name(&k)?;
+
         if BLANK_MEANS_DEFAULT_VARS.contains(&&k[..]) && v.is_empty() {
             v = self.default_vars.get(&k).unwrap().to_string();
         }
@@ -196,10 +281,45 @@ impl Vars {
             Var {
                 value: v,
                 recursive,
+                origin,
             },
         );
         Ok(())
     }
+
+    /// Append `v` to a variable, separated by a space (e.g., `VAR += value`), keeping whether the
+    /// variable is recursive. Callers must expand `v` first if the variable is not recursive. If the
+    /// variable is not defined, this is the same as a recursive assignment.
+    pub fn append<S: Into<String>>(&mut self, k: S, v: S) -> Result<(), String> {
+        let k = k.into();
+        let v = v.into();
+
+        match self.map.get(k.trim()) {
+            None => self.set(k, v, true),
+            Some(var) if var.value.is_empty() => self.set(k, v, var.recursive),
+            Some(var) => {
+                let value = format!("{} {}", var.value, v);
+                let recursive = var.recursive;
+                self.set(k, value, recursive)
+            }
+        }
+    }
+
+    /// Remove a variable entirely (e.g., `undefine VAR`), so it is no longer defined. Variables
+    /// where blank means the default value are reset to the default instead.
+    pub fn unset(&mut self, k: impl AsRef<str>) {
+        let k = k.as_ref().trim();
+
+        if BLANK_MEANS_DEFAULT_VARS.contains(&k) {
+            // This `unwrap()` is safe because these variables always have valid names.
+            let v = self.default_vars[k].clone();
+            self.set_with_origin(k.to_string(), v, false, Origin::Default)
+                .unwrap();
+            return;
+        }
+
+        self.map.remove(k);
+    }
 }
 
 impl From<Env> for Vars {
@@ -217,6 +337,7 @@ impl From<Env> for Vars {
                 Var {
                     value: v,
                     recursive: false,
+                    origin: Origin::Environment,
                 },
             );
         }
@@ -250,4 +371,83 @@ mod tests {
         vars.set(".RECIPEPREFIX", "", false).unwrap();
         assert_eq!(vars.get(".RECIPEPREFIX").value, "\t");
     }
+
+    #[test]
+    fn test_clear_builtins() {
+        let mut vars: Vars = Env::from([("CXX".to_string(), "g++".to_string())]).into();
+        vars.clear_builtins();
+        assert_eq!(vars.get("CC").value, "");
+        assert_eq!(vars.get("COMPILE.c").value, "");
+        assert_eq!(vars.get("CXX").value, "g++");
+        assert_eq!(vars.get("SHELL").value, "/bin/sh");
+        assert_eq!(vars.get(".SHELLFLAGS").value, "-c");
+        assert_eq!(vars.get(".RECIPEPREFIX").value, "\t");
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut vars = Vars::new([]);
+        vars.set("A", "$(B)", true).unwrap();
+        let a = vars.iter().find(|(k, _)| *k == "A").unwrap().1;
+        assert_eq!((a.value.as_str(), a.recursive), ("$(B)", true));
+        assert_eq!(vars.iter().count(), vars.map.len());
+    }
+
+    #[test]
+    fn test_unset() {
+        let mut vars: Vars = Env::from([("A".to_string(), "B".to_string())]).into();
+        assert_eq!(vars.get("A").origin, Origin::Environment);
+        assert_eq!(vars.get("CC").origin, Origin::Default);
+        vars.set("C", "D", false).unwrap();
+        assert_eq!(vars.get("C").origin, Origin::File);
+
+        vars.unset("A");
+        vars.unset("C");
+        assert_eq!(vars.get("A").value, "");
+        assert_eq!(vars.get("A").origin, Origin::Undefined);
+        assert_eq!(vars.get("C").origin, Origin::Undefined);
+
+        vars.set(".RECIPEPREFIX", ">", false).unwrap();
+        vars.unset(".RECIPEPREFIX");
+        assert_eq!(vars.get(".RECIPEPREFIX").value, "\t");
+    }
+
+    #[test]
+    fn test_is_set() {
+        let mut vars = Vars::new([("A", "a"), ("B", "")]);
+        vars.set("C", "$(B)", true).unwrap();
+        assert!(vars.is_set("A"));
+        assert!(!vars.is_set("B"));
+        assert!(vars.is_set("C"));
+        assert!(!vars.is_set("D"));
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut vars = Vars::new([("A", "a"), ("B", "")]);
+        assert_eq!(vars.lookup("A").map(|v| v.value.as_str()), Some("a"));
+        assert_eq!(vars.lookup(" B ").map(|v| v.origin), Some(Origin::File));
+        assert!(vars.lookup("C").is_none());
+        assert_eq!(vars.lookup("CC").map(|v| v.origin), Some(Origin::Default));
+
+        vars.unset("A");
+        assert!(vars.lookup("A").is_none());
+    }
+
+    #[test]
+    fn test_append() {
+        let mut vars = Vars::new([]);
+        vars.append("A", "a").unwrap();
+        assert_eq!(vars.get("A").value, "a");
+        assert!(vars.get("A").recursive);
+
+        vars.append("A", "$(B)").unwrap();
+        assert_eq!(vars.get("A").value, "a $(B)");
+        assert!(vars.get("A").recursive);
+
+        vars.set("C", "c", false).unwrap();
+        vars.append("C", "d").unwrap();
+        assert_eq!(vars.get("C").value, "c d");
+        assert!(!vars.get("C").recursive);
+    }
 }
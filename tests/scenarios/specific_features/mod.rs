@@ -1,4 +1,54 @@
+mod t10_keep_going;
+mod t11_equals_in_rules;
+mod t12_include;
+mod t13_no_builtin_variables;
+mod t14_line_continuation;
+mod t15_unsupported_function;
+mod t16_debug_makeflags;
+mod t17_shell_targets;
+mod t18_broken_pipe;
+mod t19_just_print_prereqs;
 mod t1_custom_recipe_prefix;
+mod t20_undefine;
+mod t21_assignment_operators;
+mod t22_leading_whitespace;
+mod t23_simple_and_recursive;
+mod t24_up_to_date;
+mod t25_wildcard;
+mod t26_command_modifiers;
+mod t27_bad_shell;
+mod t28_automatic_vars;
+mod t29_pattern_rules;
 mod t2_always_make;
+mod t30_recipe_prefix_expansion;
+mod t31_mixed_recipe_prefix;
+mod t32_phony;
+mod t33_vpath;
+mod t34_eof_backslash;
+mod t35_optional_include;
+mod t36_conditionals;
+mod t37_ifdef;
+mod t38_recipe_after_assignment;
+mod t39_parallel;
 mod t3_old_files;
+mod t40_silent;
+mod t41_intermediate;
+mod t42_sub_make;
+mod t43_expanded_prerequisites;
+mod t44_env_makeflags;
+mod t45_dialect;
+mod t46_no_rules;
+mod t47_export;
+mod t48_trace;
+mod t49_print_database;
 mod t4_just_print;
+mod t50_question;
+mod t51_default_rule;
+mod t52_precious;
+mod t53_makecmdgoals;
+mod t54_comments;
+mod t5_prerequisite_lists;
+mod t6_order_only;
+mod t7_directory;
+mod t8_automatic_var_outside_recipe;
+mod t9_oneshell;
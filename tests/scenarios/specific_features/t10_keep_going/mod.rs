@@ -0,0 +1,43 @@
+const FAILURE: &str = "make: ERROR [Makefile:7] | Failed with code 1.\n  |\n7 | b:\n  | \n\n";
+
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "echo a > a\nfalse\n",
+        expected_stderr: FAILURE,
+        expected_files: &[("a", "a\n"), ("c", "")],
+    },
+    {
+        args: &["-k"],
+        expected_stdout: "echo a > a\nfalse\necho c > c\n",
+        expected_stderr: &format!(
+            "{FAILURE}\
+            make: INFO  | Failed targets (1): 'b'.\n\
+            make: INFO  | Targets not attempted because of failed prerequisites (1): 'all'.\n\
+            make: ERROR | Target 'all' not remade because of errors.\n"
+        ),
+        expected_files: &[("a", "a\n"), ("c", "c\n")],
+    },
+    {
+        args: &["-k", "d"],
+        expected_stdout: "false\n",
+        expected_stderr: &format!(
+            "{FAILURE}\
+            make: INFO  | Failed targets (1): 'b'.\n\
+            make: INFO  | Targets not attempted because of failed prerequisites (2): 'e', 'd'.\n\
+            make: ERROR | Target 'd' not remade because of errors.\n"
+        ),
+        expected_files: &[],
+    },
+    {
+        args: &["-k", "-j2"],
+        expected_stdout: "?",
+        expected_stderr: &format!(
+            "{FAILURE}\
+            make: INFO  | Failed targets (1): 'b'.\n\
+            make: INFO  | Targets not attempted because of failed prerequisites (1): 'all'.\n\
+            make: ERROR | Target 'all' not remade because of errors.\n"
+        ),
+        expected_files: &[("a", "a\n"), ("c", "c\n")],
+    },
+);
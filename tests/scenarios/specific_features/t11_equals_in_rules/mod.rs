@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "echo a:b x=y tv\na:b x=y tv\n",
+    expected_stderr: "",
+    expected_files: &[],
+});
@@ -0,0 +1,14 @@
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "echo c\nc\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        args: &["-f", "cycle.mk"],
+        expected_stdout: "",
+        expected_stderr: "make: ERROR [b.mk:1] | Include cycle: a.mk -> b.mk -> a.mk.\n  |\n1 | include a.mk\n  | \n\n",
+        expected_files: &[],
+    },
+);
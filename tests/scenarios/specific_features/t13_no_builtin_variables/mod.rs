@@ -0,0 +1,14 @@
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "echo \"[cc]\" /bin/sh\n[cc] /bin/sh\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        args: &["-R"],
+        expected_stdout: "echo \"[]\" /bin/sh\n[] /bin/sh\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+);
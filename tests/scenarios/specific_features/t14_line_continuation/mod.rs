@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "echo a\na\necho one two \\\nthree\none two three\n",
+    expected_stderr: "",
+    expected_files: &[],
+});
@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "",
+    expected_stderr: "make: ERROR [Makefile:1] | Unsupported function 'guile'.\n  |\n1 | X := $(guile (+ 1 2))\n  | \n\n",
+    expected_files: &[],
+});
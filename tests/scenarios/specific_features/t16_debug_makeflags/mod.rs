@@ -0,0 +1,31 @@
+use std::process::Command;
+
+crate::system_test_cases!({
+    args: &["-d"],
+    expected_stdout: "echo dk\ndk\n",
+    expected_stderr: "?",
+    expected_files: &[],
+    env: &[("MAKEFLAGS", "k")],
+});
+
+/// Only the `MAKEFLAGS` line of the debug output is checked, since the rest of it (e.g., the dump
+/// of the options) changes whenever an option is added.
+#[test]
+fn test_debug_makeflags_line() {
+    let output = Command::new(env!("CARGO_BIN_EXE_omake"))
+        .arg("-d")
+        .current_dir(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/scenarios/specific_features/t16_debug_makeflags"
+        ))
+        .env("MAKEFLAGS", "k")
+        .env_remove("GNUMAKEFLAGS")
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.lines().any(|l| l == "make: INFO  | MAKEFLAGS: dk"),
+        "{stderr}"
+    );
+}
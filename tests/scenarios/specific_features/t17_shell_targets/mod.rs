@@ -0,0 +1,14 @@
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "echo hi a\nhi a\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        args: &["b"],
+        expected_stdout: "echo hi b\nhi b\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+);
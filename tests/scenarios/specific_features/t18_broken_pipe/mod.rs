@@ -0,0 +1,29 @@
+use std::process::{Command, Stdio};
+
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "sleep 0.2\ntrue\ntrue\n",
+    expected_stderr: "",
+    expected_files: &[],
+});
+
+/// Echoing commands after stdout is closed (e.g., `make | head -n 1`) should not panic.
+#[test]
+fn test_closed_stdout() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_omake"))
+        .current_dir(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/scenarios/specific_features/t18_broken_pipe"
+        ))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Close the reading end of the pipe, so the echoes after the `sleep` hit a broken pipe.
+    drop(child.stdout.take());
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &["-n"],
+    expected_stdout: "cc -c a.c\ncc -c b.c\ncc -o prog a.o b.o\n",
+    expected_stderr: "",
+    expected_files: &[("a.o", ""), ("b.o", ""), ("prog", "")],
+});
@@ -9,6 +9,16 @@ test4
 echo \"test5\"
 test5\n";
 
-crate::system_test_cases!({
-    args: &[], expected_stdout: OUTPUT, expected_stderr: "", expected_files: &[]
-});
+crate::system_test_cases!(
+    {
+        args: &[], expected_stdout: OUTPUT, expected_stderr: "", expected_files: &[]
+    },
+    {
+        // The prefix in effect is the current value, including for continued recipe lines and
+        // inline recipes, and however `.RECIPEPREFIX` is assigned.
+        args: &["-f", "switch.mk", "d"],
+        expected_stdout: "a continued\nb\nc\nd\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+);
@@ -0,0 +1,13 @@
+.RECIPEPREFIX := >
+a:
+> @echo a \
+>   continued
+.RECIPEPREFIX =
+b: a ; @echo b
+c: b
+	@echo c
+
+P = >
+.RECIPEPREFIX = $(P)
+d: c
+> @echo d
@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "echo \"[] undefined [] undefined default\"\n[] undefined [] undefined default\n",
+    expected_stderr: "",
+    expected_files: &[],
+});
@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "echo a b c d e:e t\na b c d e:e t\n",
+    expected_stderr: "",
+    expected_files: &[],
+});
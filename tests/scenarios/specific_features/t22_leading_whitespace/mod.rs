@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "echo dep\ndep\necho x inc\nx inc\n",
+    expected_stderr: "",
+    expected_files: &[],
+});
@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "echo before after all.out\nbefore after all.out\n",
+    expected_stderr: "",
+    expected_files: &[],
+});
@@ -0,0 +1,7 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "",
+    expected_stderr: "make: INFO  [Makefile:3] | Target 'x' is up to date.\n",
+    // Don't add `x` otherwise it will be removed afterwards and it's a checked-in file.
+    expected_files: &[],
+});
@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "echo src/a.c src/b.c []\nsrc/a.c src/b.c []\n",
+    expected_stderr: "",
+    expected_files: &[],
+});
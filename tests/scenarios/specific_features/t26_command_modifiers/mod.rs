@@ -0,0 +1,14 @@
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "quiet\nforced\nfalse\necho done\ndone\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        args: &["-n"],
+        expected_stdout: "false\necho quiet\nforced\nfalse\necho done\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+);
@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "echo hi\n",
+    expected_stderr: "make: ERROR [Makefile:3] | SHELL '/omake/does/not/exist' not found or not executable.\n  |\n3 | all:\n  | \n\n",
+    expected_files: &[],
+});
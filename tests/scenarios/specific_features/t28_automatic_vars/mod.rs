@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "echo all b b a / b b a b / c\nall b b a / b b a b / c\n",
+    expected_stderr: "",
+    expected_files: &[],
+});
@@ -0,0 +1 @@
+percent
@@ -0,0 +1,19 @@
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "cp foo.c foo.o\n",
+        expected_stderr: "",
+        expected_files: &[("foo.o", "foo\n")],
+    },
+    {
+        args: &["bar.o"],
+        expected_stdout: "",
+        expected_stderr: "make: ERROR | No rule to make target 'bar.o'.\n",
+        expected_files: &[("bar.o", "")],
+    },    {
+        args: &["foo.both"],
+        expected_stdout: "cat foo.c %.c > foo.both\n",
+        expected_stderr: "",
+        expected_files: &[("foo.both", "foo\npercent\n")],
+    },
+);
@@ -0,0 +1,2 @@
+TWO = ab
+.RECIPEPREFIX = $(TWO)
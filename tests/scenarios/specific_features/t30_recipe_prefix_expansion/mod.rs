@@ -0,0 +1,14 @@
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "echo a\na\necho b\nb\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        args: &["-f", "bad.mk"],
+        expected_stdout: "",
+        expected_stderr: "make: ERROR [bad.mk:2] | .RECIPEPREFIX must be a single character.\n  |\n2 | .RECIPEPREFIX = $(TWO)\n  | \n\n",
+        expected_files: &[],
+    },
+);
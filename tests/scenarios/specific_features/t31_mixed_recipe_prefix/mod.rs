@@ -0,0 +1,11 @@
+const CONTEXT: &str = "  |\n4 | \techo b\n  | \n\n";
+
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "",
+    expected_stderr: &format!(
+        "make: WARN  [Makefile:4] | Line is indented with a tab, which doesn't match the recipe prefix '>'.\n{CONTEXT}\
+        make: ERROR [Makefile:4] | Invalid line type.\n{CONTEXT}"
+    ),
+    expected_files: &[],
+});
@@ -0,0 +1,14 @@
+crate::system_test_cases!(
+    {
+        args: &["clean"],
+        expected_stdout: "echo cleaning\ncleaning\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        args: &["x"],
+        expected_stdout: "echo cleaning\ncleaning\necho x\nx\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+);
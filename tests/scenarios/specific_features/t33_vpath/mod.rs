@@ -0,0 +1,32 @@
+use std::process::Command;
+
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "cp src/a.c a.o\n",
+    expected_stderr: "",
+    expected_files: &[("a.o", "a\n")],
+});
+
+/// Under verbose debugging, the search for a prerequisite through `VPATH` should be logged.
+#[test]
+fn test_debug_verbose_vpath_probe() {
+    let output = Command::new(env!("CARGO_BIN_EXE_omake"))
+        .args(["-n", "--debug=verbose"])
+        .current_dir(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/scenarios/specific_features/t33_vpath"
+        ))
+        .env_remove("MAKEFLAGS")
+        .env_remove("GNUMAKEFLAGS")
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success());
+    assert!(stderr.contains("make: INFO  | Looking for a rule for 'a.o'.\n"));
+    // The search is cached, so `a.c` is only looked for once, even though both checking whether
+    // `a.o` is up to date and binding `$<` need to find it.
+    let probe = "make: INFO  | Looking for 'a.c' in VPATH directory 'src'.\n";
+    assert_eq!(stderr.matches(probe).count(), 1);
+    assert!(stderr.contains("make: INFO  | Found 'a.c' as 'src/a.c'.\n"));
+}
@@ -0,0 +1,14 @@
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "echo a\na\n",
+        expected_stderr: "make: WARN  [Makefile:2] | Backslash-newline at end of file.\n  |\n2 | \techo a \\\n  | \n\n",
+        expected_files: &[],
+    },
+    {
+        args: &["-f", "var.mk"],
+        expected_stdout: "echo a\na\n",
+        expected_stderr: "make: WARN  [var.mk:3] | Backslash-newline at end of file.\n  |\n3 | X = a \\\n  | \n\n",
+        expected_files: &[],
+    },
+);
@@ -0,0 +1,3 @@
+all:
+	echo $(X)
+X = a \
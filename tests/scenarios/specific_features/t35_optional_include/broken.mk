@@ -0,0 +1 @@
+-include bad.mk
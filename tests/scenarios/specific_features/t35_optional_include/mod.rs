@@ -0,0 +1,14 @@
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "echo other\nother\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        args: &["-f", "broken.mk"],
+        expected_stdout: "",
+        expected_stderr: "make: ERROR [bad.mk:1] | Invalid line type.\n  |\n1 | this is not valid\n  | \n\n",
+        expected_files: &[],
+    },
+);
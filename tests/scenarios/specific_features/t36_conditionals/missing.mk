@@ -0,0 +1,3 @@
+ifeq (a,a)
+all:
+	echo a
@@ -0,0 +1,33 @@
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "echo eq right\neq right\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        args: &["-f", "whitespace.mk"],
+        expected_stdout: "nonempty empty\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        // Tab-indented directives within a recipe are passed to the shell (which rejects them).
+        args: &["-i", "-f", "recipe.mk"],
+        expected_stdout: "ifeq (a,b)\necho inside\ninside\nendif\n",
+        expected_stderr: "?",
+        expected_files: &[],
+    },
+    {
+        args: &["-f", "unmatched.mk"],
+        expected_stdout: "",
+        expected_stderr: "make: ERROR [unmatched.mk:3] | Unmatched 'endif'.\n  |\n3 | endif\n  | \n\n",
+        expected_files: &[],
+    },
+    {
+        args: &["-f", "missing.mk"],
+        expected_stdout: "",
+        expected_stderr: "make: ERROR [missing.mk:1] | Missing 'endif'.\n  |\n1 | ifeq (a,a)\n  | \n\n",
+        expected_files: &[],
+    },
+);
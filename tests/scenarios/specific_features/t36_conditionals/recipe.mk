@@ -0,0 +1,4 @@
+all:
+	ifeq (a,b)
+	echo inside
+	endif
@@ -0,0 +1,3 @@
+all:
+	echo a
+endif
@@ -0,0 +1,19 @@
+empty :=
+space := $(empty) $(empty)
+
+# A value which expands to only whitespace is not empty.
+ifeq ($(space),)
+SPACE = empty
+else
+SPACE = nonempty
+endif
+
+# Whitespace around the (unexpanded) arguments is ignored.
+ifeq ( $(empty) , )
+EMPTY = empty
+else
+EMPTY = nonempty
+endif
+
+all:
+	@echo $(SPACE) $(EMPTY)
@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "echo direct indirect ifndef nested\ndirect indirect ifndef nested\n",
+    expected_stderr: "",
+    expected_files: &[],
+});
@@ -0,0 +1,3 @@
+# A recipe line before any rule.
+	  echo a
+all:
@@ -0,0 +1,16 @@
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "",
+        expected_stderr: "make: WARN  [Makefile:3] | This line is a variable assignment, not a rule.\n  |\n3 | VAR = x\n  | \n\n\
+            make: ERROR [Makefile:4:2] | Recipe without rule (the preceding line is a variable assignment).\n  |\n4 | \techo $(VAR)\n  | \t^\n\n",
+        expected_files: &[],
+    },
+    {
+        // The caret points at the start of the recipe text, after the recipe prefix.
+        args: &["-f", "first.mk"],
+        expected_stdout: "",
+        expected_stderr: "make: ERROR [first.mk:2:4] | Recipe commences before first target.\n  |\n2 | \t  echo a\n  | \t  ^\n\n",
+        expected_files: &[],
+    },
+);
@@ -0,0 +1,87 @@
+use std::fs;
+use std::process::{Command, Output};
+
+const DIR: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/scenarios/specific_features/t39_parallel"
+);
+
+/// Run `omake` in this scenario, using `mark` to name the marker files so tests can run at once.
+fn run(mark: &str, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_omake"))
+        .args(args)
+        .current_dir(DIR)
+        .env("MARK", mark)
+        .env("OMAKE", env!("CARGO_BIN_EXE_omake"))
+        .env_remove("MAKEFLAGS")
+        .env_remove("GNUMAKEFLAGS")
+        .output()
+        .unwrap();
+
+    for target in ["a", "b", "later", "x", "y"] {
+        let _ = fs::remove_file(format!("{DIR}/{mark}.{target}.started"));
+    }
+
+    output
+}
+
+/// Independent prerequisites are made concurrently, and a shared prerequisite is only made once.
+#[test]
+fn test_parallel_prerequisites() {
+    let output = run("parallel", &["-j2"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert!(output.status.success());
+
+    let mut lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.remove(0), "shared");
+    assert_eq!(lines.pop(), Some("all"));
+    lines.sort();
+    assert_eq!(lines, ["a", "b"]);
+}
+
+/// The implicit job slot is reused once its job is done, even while other jobs hold tokens.
+#[test]
+fn test_parallel_implicit_slot_reused() {
+    let output = run("implicit", &["-j2", "implicit"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert!(output.status.success());
+
+    let mut lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.remove(0), "quick");
+    assert_eq!(lines.pop(), Some("implicit"));
+    lines.sort();
+    assert_eq!(lines, ["later", "slow"]);
+}
+
+/// A recursive sub-make gets the jobserver from `MAKEFLAGS`, so it can run its jobs in parallel
+/// using tokens from the parent's pool.
+#[test]
+fn test_sub_make_fifo_jobserver() {
+    let output = run("fifo", &["-j3", "recursive"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert!(output.status.success());
+
+    let mut lines = stdout.lines().collect::<Vec<_>>();
+    assert!(lines.remove(0).starts_with("-j3 --jobserver-auth=fifo:"));
+    lines.sort();
+    assert_eq!(lines, ["x", "y"]);
+}
+
+/// The legacy style passes the jobserver as inherited file descriptors.
+#[test]
+fn test_sub_make_pipe_jobserver() {
+    let output = run("pipe", &["-j2", "--jobserver-style=pipe", "recursive"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert!(output.status.success());
+
+    let mut lines = stdout.lines().collect::<Vec<_>>();
+    let makeflags = lines.remove(0);
+    let auth = makeflags.strip_prefix("-j2 --jobserver-auth=").unwrap();
+    assert!(auth.split_once(',').is_some());
+    lines.sort();
+    assert_eq!(lines, ["x", "y"]);
+}
@@ -0,0 +1,8 @@
+# A sub-make whose jobs wait for each other, so it needs a token from the parent's jobserver.
+all: x y
+
+x y:
+	@touch $(MARK).$@.started; i=0; until [ -e $(MARK).$(OTHER_$@).started ]; do i=$$((i+1)); [ $$i -gt 500 ] && exit 1; sleep 0.01; done; echo $@
+
+OTHER_x = y
+OTHER_y = x
@@ -0,0 +1,4 @@
+.SILENT:
+
+all:
+	echo all
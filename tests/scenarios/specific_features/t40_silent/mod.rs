@@ -0,0 +1,26 @@
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "echo a\na\nb\necho all\nall\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        args: &["-s"],
+        expected_stdout: "a\nb\nall\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        args: &["--quiet", "-n"],
+        expected_stdout: "echo a\necho b\necho all\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        args: &["-f", "bare.mk"],
+        expected_stdout: "all\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+);
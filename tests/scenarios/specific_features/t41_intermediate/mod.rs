@@ -0,0 +1,25 @@
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "echo a > a\ncp a b\nall\n",
+        expected_stderr: "make: INFO  | Removing intermediate file 'a'.\n",
+        expected_files: &[("a", ""), ("b", "a\n")],
+    },
+    {
+        args: &["c"],
+        expected_stdout: "echo a > a\nfalse\n",
+        expected_stderr: "make: INFO  | Removing intermediate file 'a'.\n\
+            make: ERROR [Makefile:10] | Failed with code 1.\n   |\n10 | c: a\n   | \n\n",
+        expected_files: &[("a", "")],
+    },
+    {
+        args: &[],
+        expected_stdout: "cp a b\nall\n",
+        expected_stderr: "",
+        expected_files: &[("a", "x\n"), ("b", "x\n")],
+        pre_hook: {
+            // An intermediate file which existed before the run is kept.
+            std::fs::write("tests/scenarios/specific_features/t41_intermediate/a", "x\n").unwrap();
+        },
+    },
+);
@@ -0,0 +1,45 @@
+use std::process::{Command, Output};
+
+/// Run `omake` in this scenario, so `$(MAKE)` is the (absolute) path to the test binary.
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_omake"))
+        .args(args)
+        .current_dir(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/scenarios/specific_features/t42_sub_make"
+        ))
+        .env_remove("MAKEFLAGS")
+        .env_remove("GNUMAKEFLAGS")
+        .output()
+        .unwrap()
+}
+
+/// `$(MAKE)` runs this program again, in the directory given by `-C`.
+#[test]
+fn test_sub_make() {
+    let output = run(&[]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "{} -C sub\necho sub\nsub\ndone\n",
+            env!("CARGO_BIN_EXE_omake")
+        )
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("make: INFO  | Chdir to `sub`.\n"));
+}
+
+/// A line which runs `$(MAKE)` is run even when just printing, and the sub-make inherits `-n` from
+/// `MAKEFLAGS`, so it only prints its own commands.
+#[test]
+fn test_sub_make_just_print() {
+    let output = run(&["-n"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "{} -C sub\necho sub\necho done\n",
+            env!("CARGO_BIN_EXE_omake")
+        )
+    );
+}
@@ -0,0 +1,28 @@
+crate::system_test_cases!(
+    {
+        args: &["empty"],
+        expected_stdout: "empty\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        // With no prerequisites after expansion, an existing target is up to date.
+        args: &["empty"],
+        expected_stdout: "",
+        expected_stderr: "make: INFO  [Makefile:4] | Target 'empty' is up to date.\n",
+        expected_files: &[("empty", "")],
+        pre_hook: {
+            std::fs::write(
+                "tests/scenarios/specific_features/t43_expanded_prerequisites/empty",
+                "",
+            )
+            .unwrap();
+        },
+    },
+    {
+        args: &["multi"],
+        expected_stdout: "x\ny\nx y\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+);
@@ -0,0 +1,49 @@
+use std::process::Command;
+
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "echo all\n",
+        expected_stderr: "",
+        expected_files: &[],
+        env: &[("MAKEFLAGS", "n")],
+    },
+    {
+        // Flags on the command line win over conflicting flags from the environment (see below).
+        args: &["-d"],
+        expected_stdout: "echo all\n",
+        expected_stderr: "?",
+        expected_files: &[],
+        env: &[("MAKEFLAGS", "n --debug=verbose")],
+    },
+    {
+        args: &[],
+        expected_stdout: "echo all\nall\n",
+        expected_stderr: "make: WARN  | Ignoring invalid flags from MAKEFLAGS.\n",
+        expected_files: &[],
+        env: &[("MAKEFLAGS", "--no-such-flag")],
+    },
+);
+
+/// The `-d` on the command line replaces the `--debug=verbose` from the environment. Only the
+/// `MAKEFLAGS` line of the debug output is checked, since the rest of it changes whenever an option
+/// is added.
+#[test]
+fn test_command_line_flags_win() {
+    let output = Command::new(env!("CARGO_BIN_EXE_omake"))
+        .arg("-d")
+        .current_dir(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/scenarios/specific_features/t44_env_makeflags"
+        ))
+        .env("MAKEFLAGS", "n --debug=verbose")
+        .env_remove("GNUMAKEFLAGS")
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.lines().any(|l| l == "make: INFO  | MAKEFLAGS: dn"),
+        "{stderr}"
+    );
+}
@@ -0,0 +1,17 @@
+A ?= a
+N = 3
+
+.if ${A} == "a" && ${N} > 2
+RESULT = yes
+.elif defined(B)
+RESULT = elif
+.else
+RESULT = no
+.endif
+
+.ifndef B
+.  include "inc.mk"
+.endif
+
+all:
+	@echo $(RESULT) $(INC)
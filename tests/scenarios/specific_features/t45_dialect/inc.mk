@@ -0,0 +1 @@
+INC = included
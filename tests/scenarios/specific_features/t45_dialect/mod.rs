@@ -0,0 +1,28 @@
+crate::system_test_cases!(
+    {
+        args: &["--dialect=bsd", "-f", "bsd.mk"],
+        expected_stdout: "yes included\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        // The dialect is detected from the first directive.
+        args: &["-f", "bsd.mk"],
+        expected_stdout: "yes included\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        args: &["--dialect=gnu", "-f", "bsd.mk"],
+        expected_stdout: "",
+        expected_stderr: "make: ERROR [bsd.mk:4] | BSD directive '.if' is only supported with `--dialect=bsd`.\n  |\n4 | .if ${A} == \"a\" && ${N} > 2\n  | \n\n",
+        expected_files: &[],
+    },
+    {
+        args: &["--dialect=bsd", "-f", "bsd.mk"],
+        expected_stdout: "elif\n",
+        expected_stderr: "",
+        expected_files: &[],
+        env: &[("A", "b"), ("B", "1")],
+    },
+);
@@ -0,0 +1,21 @@
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "",
+        expected_stderr: "make: ERROR | No target specified and the makefile defines no rules.\n",
+        expected_files: &[],
+    },
+    {
+        // Pattern rules can't be the default goal, but they are still rules.
+        args: &["-f", "patterns.mk"],
+        expected_stdout: "",
+        expected_stderr: "make: ERROR | No target specified and no default target found.\n",
+        expected_files: &[],
+    },
+    {
+        args: &["--require-rules", "all"],
+        expected_stdout: "",
+        expected_stderr: "make: ERROR [Makefile] | Makefile defines no rules.\n",
+        expected_files: &[],
+    },
+);
@@ -0,0 +1,3 @@
+.PHONY: all
+%.o: %.c
+	cc $<
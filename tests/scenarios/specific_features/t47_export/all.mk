@@ -0,0 +1,8 @@
+export
+A = a
+B = $(A)b
+C = c
+unexport C FROM_ENV
+
+all:
+	@echo A=$$A B=$$B C=$$C FROM_ENV=$$FROM_ENV
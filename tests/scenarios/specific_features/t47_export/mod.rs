@@ -0,0 +1,32 @@
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "/bin/sh hello world\n",
+        expected_stderr: "",
+        expected_files: &[],
+        env: &[("SHELL", "/not/a/shell")],
+    },
+    {
+        // Recipes are still run with the makefile's `SHELL`, but see the environment's value.
+        args: &["-f", "unexported.mk"],
+        expected_stdout: "/not/a/shell\n",
+        expected_stderr: "",
+        expected_files: &[],
+        env: &[("SHELL", "/not/a/shell")],
+    },
+    {
+        // A bare `export` exports every variable, except those which are unexported, which also
+        // removes variables from the environment.
+        args: &["-f", "all.mk"],
+        expected_stdout: "A=a B=ab C= FROM_ENV=\n",
+        expected_stderr: "",
+        expected_files: &[],
+        env: &[("FROM_ENV", "e")],
+    },
+    {
+        args: &["-f", "unexport_all.mk"],
+        expected_stdout: "A=\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+);
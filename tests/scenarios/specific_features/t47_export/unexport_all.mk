@@ -0,0 +1,6 @@
+export
+A = a
+unexport
+
+all:
+	@echo A=$$A
@@ -0,0 +1,5 @@
+SHELL = /bin/sh
+GREETING = hello
+
+all:
+	@echo $$SHELL $$GREETING
@@ -0,0 +1,55 @@
+/// Write `path` with the given `mtime` (in seconds since the epoch), so the trace is deterministic.
+fn write_with_mtime(path: &str, mtime: u64) {
+    let file = std::fs::File::create(path).unwrap();
+    let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime);
+    file.set_modified(mtime).unwrap();
+}
+
+crate::system_test_cases!(
+    {
+        args: &["--trace"],
+        expected_stdout: concat!(
+            "make: INFO  [Makefile:4] | Remaking 'in' because it does not exist.\n",
+            "make: INFO  [Makefile:1] | Remaking 'out' because it does not exist.\n",
+        ),
+        expected_stderr: "",
+        expected_files: &[("in", "in\n"), ("out", "out\n")],
+    },
+    {
+        args: &["--trace"],
+        expected_stdout: concat!(
+            "make: INFO  [Makefile:1] | Remaking 'out' because prerequisite 'in' ",
+            "(2000000.000000000) is newer than the target (1000000.000000000).\n",
+        ),
+        expected_stderr: "",
+        expected_files: &[("in", ""), ("out", "out\n")],
+        pre_hook: {
+            write_with_mtime("tests/scenarios/specific_features/t48_trace/in", 2_000_000);
+            write_with_mtime("tests/scenarios/specific_features/t48_trace/out", 1_000_000);
+        },
+    },
+    {
+        args: &["--trace", "-B"],
+        expected_stdout: concat!(
+            "make: INFO  [Makefile:4] | Remaking 'in' because all targets are remade (-B).\n",
+            "make: INFO  [Makefile:1] | Remaking 'out' because all targets are remade (-B).\n",
+        ),
+        expected_stderr: "",
+        expected_files: &[("in", "in\n"), ("out", "out\n")],
+        pre_hook: {
+            write_with_mtime("tests/scenarios/specific_features/t48_trace/in", 1_000_000);
+            write_with_mtime("tests/scenarios/specific_features/t48_trace/out", 2_000_000);
+        },
+    },
+    // Like GNU `make`, trace lines go to `stdout` ahead of the echoed recipe, and only the recipe's
+    // own error output goes to `stderr`.
+    {
+        args: &["--trace", "loud"],
+        expected_stdout: concat!(
+            "make: INFO  [Makefile:7] | Remaking 'loud' because it does not exist.\n",
+            "echo loud\nloud\necho err >&2\n",
+        ),
+        expected_stderr: "err\n",
+        expected_files: &[("loud", "")],
+    },
+);
@@ -0,0 +1,32 @@
+use std::path::Path;
+use std::process::Command;
+
+const DIR: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/scenarios/specific_features/t49_print_database"
+);
+
+/// `-p` prints the variables and rules, without making any targets.
+#[test]
+fn test_print_database() {
+    let output = Command::new(env!("CARGO_BIN_EXE_omake"))
+        .args(["-p", "-R"])
+        .current_dir(DIR)
+        .env_remove("MAKEFLAGS")
+        .env_remove("GNUMAKEFLAGS")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert!(output.status.success());
+
+    assert!(stdout.starts_with("# Variables\n"));
+    assert!(stdout.contains("\n# file\nA = $(B)\n"));
+    assert!(stdout.contains("\n# file\nB := b\n"));
+    assert!(stdout.ends_with(concat!(
+        "\n# Rules\n",
+        "\n# Makefile:4\nall: out | dir\n\t@echo $(A) > out\n",
+        "\n# Makefile:7\n%.o: %.c\n\tcc -c $<\n",
+    )));
+    assert!(!Path::new(DIR).join("out").exists());
+}
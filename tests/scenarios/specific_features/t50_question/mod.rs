@@ -0,0 +1,55 @@
+use std::fs::{self, File};
+use std::process::{Command, Output};
+use std::time::{Duration, UNIX_EPOCH};
+
+const DIR: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/scenarios/specific_features/t50_question"
+);
+
+/// Write `{name}.in` and `{name}.out` with the given `mtime`s (in seconds since the epoch), then
+/// run `omake -q {name}.out`, and remove the files again.
+fn question(name: &str, in_mtime: u64, out_mtime: u64) -> Output {
+    for (ext, mtime) in [("in", in_mtime), ("out", out_mtime)] {
+        let path = format!("{DIR}/{name}.{ext}");
+        fs::write(&path, "old\n").unwrap();
+        let mtime = UNIX_EPOCH + Duration::from_secs(mtime);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_omake"))
+        .args(["-q", &format!("{name}.out")])
+        .current_dir(DIR)
+        .env_remove("MAKEFLAGS")
+        .env_remove("GNUMAKEFLAGS")
+        .output()
+        .unwrap();
+
+    // The recipe must not have run.
+    let out = fs::read_to_string(format!("{DIR}/{name}.out")).unwrap();
+    for ext in ["in", "out"] {
+        fs::remove_file(format!("{DIR}/{name}.{ext}")).unwrap();
+    }
+    assert_eq!(out, "old\n");
+
+    output
+}
+
+#[test]
+fn test_question_up_to_date() {
+    let output = question("fresh", 1_000, 2_000);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_question_needs_rebuild() {
+    let output = question("stale", 2_000, 1_000);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(output.status.code(), Some(1));
+}
@@ -0,0 +1,14 @@
+crate::system_test_cases!(
+    {
+        args: &["anything"],
+        expected_stdout: "default for anything\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        args: &[],
+        expected_stdout: "default for missing\nall\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+);
@@ -0,0 +1,21 @@
+crate::system_test_cases!(
+    {
+        args: &["deleted"],
+        expected_stdout: "",
+        expected_stderr: concat!(
+            "make: INFO  | Deleting file 'deleted'.\n",
+            "make: ERROR [Makefile:3] | Failed with code 1.\n",
+            "  |\n3 | deleted kept:\n  | \n\n",
+        ),
+        expected_files: &[("deleted", "")],
+    },
+    {
+        args: &["kept"],
+        expected_stdout: "",
+        expected_stderr: concat!(
+            "make: ERROR [Makefile:3] | Failed with code 1.\n",
+            "  |\n3 | deleted kept:\n  | \n\n",
+        ),
+        expected_files: &[("kept", "partial\n")],
+    },
+);
@@ -0,0 +1,23 @@
+crate::system_test_cases!(
+    {
+        args: &[],
+        expected_stdout: "building all [] undefined\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        args: &["clean"],
+        expected_stdout: "cleaning clean [clean] default\n",
+        expected_stderr: "",
+        expected_files: &[],
+    },
+    {
+        args: &["all", "clean"],
+        expected_stdout: concat!(
+            "building all [all clean] default\n",
+            "building clean [all clean] default\n",
+        ),
+        expected_stderr: "",
+        expected_files: &[],
+    },
+);
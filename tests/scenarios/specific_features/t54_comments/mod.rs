@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "# not a comment\n[1 ] [x#y] [a-b ] [a b ] dep\n",
+    expected_stderr: "",
+    expected_files: &[],
+});
@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "echo a b\na b\necho a b a\na b a\n",
+    expected_stderr: "",
+    expected_files: &[],
+});
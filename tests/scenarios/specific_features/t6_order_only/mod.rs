@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "echo b > b\necho a\na\necho b\nb\n",
+    expected_stderr: "",
+    expected_files: &[("b", "b\n")],
+});
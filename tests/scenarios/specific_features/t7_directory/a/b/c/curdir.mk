@@ -0,0 +1,6 @@
+all:
+ifeq ($(shell pwd),$(CURDIR))
+	@echo same $(notdir $(CURDIR))
+else
+	@echo different
+endif
@@ -0,0 +1,27 @@
+crate::system_test_cases!(
+    {
+        args: &["-C", "a/b/c"],
+        expected_stdout: "echo \"in c\"\nin c\n",
+        expected_stderr: "?",
+        expected_files: &[],
+    },
+    {
+        args: &["-C", "a", "-C", "b", "-C", "c"],
+        expected_stdout: "echo \"in c\"\nin c\n",
+        expected_stderr: "?",
+        expected_files: &[],
+    },
+    {
+        args: &["-C", "a/b", "-C", "c"],
+        expected_stdout: "echo \"in c\"\nin c\n",
+        expected_stderr: "?",
+        expected_files: &[],
+    },
+    {
+        // `$(shell)` runs in the new directory, which is also `CURDIR`.
+        args: &["-C", "a/b/c", "-f", "curdir.mk"],
+        expected_stdout: "same c\n",
+        expected_stderr: "?",
+        expected_files: &[],
+    },
+);
@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "echo all\nall\n",
+    expected_stderr: "make: WARN  [Makefile:1] | Automatic variable `$@` is only set within recipes.\n  |\n1 | all: $@\n  | \n\n",
+    expected_files: &[],
+});
@@ -0,0 +1,6 @@
+crate::system_test_cases!({
+    args: &[],
+    expected_stdout: "echo one\n\necho two\none\ntwo\n",
+    expected_stderr: "",
+    expected_files: &[],
+});
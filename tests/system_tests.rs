@@ -16,6 +16,8 @@ struct SystemTestCase {
     pub expected_stderr: String,
     /// Expected files that should be created, mapped to their content.
     pub expected_files: HashMap<String, String>,
+    /// Environment variables to set for `omake`.
+    pub env: Vec<(String, String)>,
 }
 
 impl SystemTestCase {
@@ -28,6 +30,7 @@ impl SystemTestCase {
         expected_stdout: &str,
         expected_stderr: &str,
         expected_files: &[(&str, &str)],
+        env: &[(&str, &str)],
     ) {
         let system_test = Self {
             // Trim leading/trailing slashes in `path`.
@@ -39,6 +42,10 @@ impl SystemTestCase {
                 .iter()
                 .map(|(f, c)| (f.to_string(), c.to_string()))
                 .collect::<HashMap<_, _>>(),
+            env: env
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
         };
         system_test.run();
     }
@@ -53,9 +60,13 @@ impl SystemTestCase {
             "../".repeat(self.path.matches('/').count() + 1),
         );
 
-        // Run `omake` inside the system test directory.
+        // Run `omake` inside the system test directory. Flags are removed from the environment so
+        // tests behave the same when run by `make`.
         let output = Command::new(omake_path)
             .args(&self.args)
+            .env_remove("MAKEFLAGS")
+            .env_remove("GNUMAKEFLAGS")
+            .envs(self.env.iter().map(|(k, v)| (k, v)))
             .current_dir(self.relative_path(&"".to_string()))
             .output()
             .unwrap();
@@ -114,6 +125,7 @@ macro_rules! system_test_cases {
             expected_stdout: $expected_stdout:expr,
             expected_stderr: $expected_stderr:expr,
             expected_files: $expected_files:expr
+            $(, env: $env:expr)?
             $(, pre_hook: $pre_hook:block)?
             $(, post_hook: $post_hook:block)?
             $(,)?
@@ -129,8 +141,11 @@ macro_rules! system_test_cases {
             // Run the specified test cases.
             $(
                 $($pre_hook)?
+                #[allow(unused_variables)]
+                let env: &[(&str, &str)] = &[];
+                $(let env: &[(&str, &str)] = $env;)?
                 crate::SystemTestCase::execute(
-                    &path, $args, $expected_stdout, $expected_stderr, $expected_files
+                    &path, $args, $expected_stdout, $expected_stderr, $expected_files, env
                 );
                 $($post_hook)?
             )*